@@ -0,0 +1,219 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A bump allocator operating on a fixed size arena.
+//!
+//! The arena is reserved at compile time as part of the static memory of the contract, and the
+//! allocator never requests additional pages of Wasm memory. This gives contracts fully
+//! predictable memory behavior: once the arena is exhausted every further allocation fails,
+//! which for a global allocator means that the contract traps.
+
+use core::{
+    alloc::{
+        GlobalAlloc,
+        Layout,
+    },
+    cell::UnsafeCell,
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+/// The memory backing a [`StaticBumpAllocator`].
+///
+/// Aligned so that allocations of the common primitive types never need to skip any bytes at
+/// the start of the arena.
+#[repr(C, align(16))]
+struct Arena<const BYTES: usize>([u8; BYTES]);
+
+/// A bump allocator which hands out memory from a fixed arena of `BYTES` bytes.
+///
+/// # Usage
+///
+/// Enable the `static-arena` crate feature so that ink! does not install its own global
+/// allocator, then install the arena in the contract:
+///
+/// ```ignore
+/// #[global_allocator]
+/// static ALLOC: ink_allocator::StaticBumpAllocator<{ 64 * 1024 }> =
+///     ink_allocator::StaticBumpAllocator::new();
+/// ```
+pub struct StaticBumpAllocator<const BYTES: usize> {
+    arena: UnsafeCell<Arena<BYTES>>,
+    /// The offset of the next available byte within the arena.
+    next: AtomicUsize,
+}
+
+// The offset is only ever bumped atomically, so concurrent allocations are handed out disjoint
+// parts of the arena.
+unsafe impl<const BYTES: usize> Sync for StaticBumpAllocator<BYTES> {}
+
+impl<const BYTES: usize> StaticBumpAllocator<BYTES> {
+    /// Creates a new allocator with an empty, zero initialized arena.
+    pub const fn new() -> Self {
+        Self {
+            arena: UnsafeCell::new(Arena([0; BYTES])),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of bytes in the arena which have not been handed out yet.
+    ///
+    /// Note that an allocation of this size might still fail if it requires some padding to
+    /// satisfy its alignment.
+    pub fn remaining(&self) -> usize {
+        BYTES - self.next.load(Ordering::Relaxed)
+    }
+
+    /// Tries to bump the arena for the given `Layout`.
+    ///
+    /// Returns `None` if the arena does not have enough room left.
+    fn bump(&self, layout: Layout) -> Option<usize> {
+        let base = self.arena.get() as usize;
+        let mut alloc_start = 0;
+        self.next
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |next| {
+                let unaligned = base.checked_add(next)?;
+                let start = unaligned
+                    .checked_add(unaligned.wrapping_neg() & (layout.align() - 1))?;
+                let alloc_end = start.checked_add(layout.pad_to_align().size())?;
+
+                if alloc_end > base.checked_add(BYTES)? {
+                    return None
+                }
+
+                alloc_start = start;
+                Some(alloc_end - base)
+            })
+            .ok()?;
+        Some(alloc_start)
+    }
+}
+
+impl<const BYTES: usize> Default for StaticBumpAllocator<BYTES> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+unsafe impl<const BYTES: usize> GlobalAlloc for StaticBumpAllocator<BYTES> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match self.bump(layout) {
+            Some(start) => start as *mut u8,
+            None => core::ptr::null_mut(),
+        }
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        // The arena starts out zero initialized and bytes are never handed out twice, so every
+        // allocation is already zeroed.
+        self.alloc(layout)
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn can_alloc_up_to_capacity() {
+        let arena = StaticBumpAllocator::<64>::new();
+        let layout = Layout::new::<u64>();
+
+        for _ in 0..8 {
+            let ptr = unsafe { arena.alloc(layout) };
+            assert!(!ptr.is_null());
+            assert_eq!(ptr as usize % layout.align(), 0);
+        }
+        assert_eq!(arena.remaining(), 0);
+
+        // The arena is exhausted, so even the smallest allocation has to fail
+        let ptr = unsafe { arena.alloc(Layout::new::<u8>()) };
+        assert!(ptr.is_null());
+    }
+
+    #[test]
+    fn can_alloc_the_whole_arena_at_once() {
+        let arena = StaticBumpAllocator::<128>::new();
+
+        let layout = Layout::new::<[u8; 128]>();
+        let ptr = unsafe { arena.alloc(layout) };
+        assert_eq!(ptr as usize, arena.arena.get() as usize);
+
+        let ptr = unsafe { arena.alloc(Layout::new::<u8>()) };
+        assert!(ptr.is_null());
+    }
+
+    #[test]
+    fn cannot_alloc_more_than_capacity() {
+        let arena = StaticBumpAllocator::<64>::new();
+
+        let ptr = unsafe { arena.alloc(Layout::new::<[u8; 65]>()) };
+        assert!(ptr.is_null());
+
+        // A failed allocation must not consume any of the arena
+        assert_eq!(arena.remaining(), 64);
+    }
+
+    #[test]
+    fn alignment_padding_counts_against_capacity() {
+        let arena = StaticBumpAllocator::<32>::new();
+
+        let first = unsafe { arena.alloc(Layout::new::<u8>()) };
+        assert!(!first.is_null());
+
+        // After a single byte we need to skip 15 bytes to get to a 16 byte boundary
+        let layout = Layout::from_size_align(16, 16).unwrap();
+        let second = unsafe { arena.alloc(layout) };
+        assert_eq!(second as usize, first as usize + 16);
+        assert_eq!(arena.remaining(), 0);
+
+        let third = unsafe { arena.alloc(Layout::new::<u8>()) };
+        assert!(third.is_null());
+    }
+
+    #[test]
+    fn concurrent_allocations_never_overlap() {
+        static ARENA: StaticBumpAllocator<{ 4 * 1024 }> = StaticBumpAllocator::new();
+        let layout = Layout::new::<u64>();
+
+        let threads = (0..4)
+            .map(|_| {
+                std::thread::spawn(move || {
+                    (0..128)
+                        .map(|_| unsafe { ARENA.alloc(layout) } as usize)
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut starts = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect::<Vec<_>>();
+        starts.sort_unstable();
+
+        // The threads exhausted the arena between them without handing out any byte twice
+        assert!(starts.iter().all(|&start| start != 0));
+        assert!(starts
+            .windows(2)
+            .all(|pair| pair[1] - pair[0] == layout.size()));
+        assert_eq!(ARENA.remaining(), 0);
+    }
+}
@@ -20,261 +20,4942 @@
 //! The heap which is used by this allocator is built from pages of Wasm memory (each page is `64KiB`).
 //! We will request new pages of memory as needed until we run out of memory, at which point we
 //! will crash with an `OOM` error instead of freeing any memory.
+//!
+//! Any overflow while computing the addresses of an allocation is treated as running out of
+//! memory: the allocation fails and the heap is left exactly as it was. We never saturate an
+//! address at the end of the address space, since the saturated address would describe memory
+//! which does not belong to the allocation.
+
+use crate::error::{
+    layout,
+    AllocError,
+};
+#[cfg(feature = "debug-log")]
+use crate::event_log::{
+    AllocEvent,
+    EventLog,
+};
+#[cfg(feature = "free-list")]
+use crate::free_list::FreeList;
+#[cfg(feature = "export-metrics")]
+use crate::metrics;
+#[cfg(feature = "observer")]
+use crate::observer::AllocObserver;
+#[cfg(feature = "size-classes")]
+use crate::size_classes::SizeClasses;
+#[cfg(feature = "stats")]
+use crate::stats::AllocStats;
+#[cfg(feature = "debug-tracking")]
+use crate::tracking::LiveAllocations;
+use core::alloc::{
+    GlobalAlloc,
+    Layout,
+};
+
+/// A page in Wasm is `64KiB`
+pub(crate) const PAGE_SIZE: usize = 64 * 1024;
+
+#[cfg(not(feature = "export-metrics"))]
+static mut GLOBAL: Bump<Pages> = Bump::new(Pages::new()).deferring_traps();
+#[cfg(feature = "export-metrics")]
+static mut GLOBAL: Bump<Pages> = Bump::new(Pages::new())
+    .deferring_traps()
+    .exporting_metrics();
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use core::{
+            cell::Cell,
+            sync::atomic::{
+                AtomicBool,
+                Ordering,
+            },
+        };
+
+        /// Whether some thread is accessing the global heap, since off-chain it may be shared by
+        /// multiple threads.
+        ///
+        /// This is a spin lock rather than a `Mutex`, since locking must not allocate any memory
+        /// while the global heap might back the global allocator.
+        static LOCKED: AtomicBool = AtomicBool::new(false);
+
+        std::thread_local! {
+            /// Whether the current thread is accessing the global heap already.
+            static ACCESSING: Cell<bool> = const { Cell::new(false) };
+        }
+
+        /// Releases the global heap once dropped, even when unwinding.
+        struct Unlock;
+
+        impl Drop for Unlock {
+            fn drop(&mut self) {
+                ACCESSING.with(|accessing| accessing.set(false));
+                LOCKED.store(false, Ordering::Release);
+            }
+        }
+
+        /// Runs `f` on the global heap while holding the lock.
+        ///
+        /// # Panics
+        ///
+        /// If the global heap is accessed again while `f` is running on the same thread, e.g. by
+        /// the panic hook of a panic within the heap. Waiting for ourselves would never end.
+        fn access_global<R>(f: impl FnOnce(&mut Bump<Pages>) -> R) -> R {
+            assert!(
+                !ACCESSING.with(Cell::get),
+                "the global heap has been accessed reentrantly"
+            );
+            while LOCKED
+                .compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed)
+                .is_err()
+            {
+                core::hint::spin_loop();
+            }
+            ACCESSING.with(|accessing| accessing.set(true));
+            let _unlock = Unlock;
+            // SAFETY: We hold the lock, so nobody else is accessing the global heap.
+            f(unsafe { &mut *core::ptr::addr_of_mut!(GLOBAL) })
+        }
+    } else {
+        /// Runs `f` on the global heap.
+        #[inline]
+        fn access_global<R>(f: impl FnOnce(&mut Bump<Pages>) -> R) -> R {
+            // SAFETY: On-chain there is only a single thread, see [`BumpAllocator`].
+            f(unsafe { &mut *core::ptr::addr_of_mut!(GLOBAL) })
+        }
+    }
+}
+
+/// Runs `f` on the heap which is shared by all users of the [`BumpAllocator`].
+///
+/// # Panics
+///
+/// If `f` ran into a trap, e.g. by growing the heap while growing is forbidden. The trap is only
+/// raised once the global heap has been released again, so that the panic machinery can still
+/// use it.
+#[inline]
+fn with_global<R>(f: impl FnOnce(&mut Bump<Pages>) -> R) -> R {
+    let (result, trap) = access_global(|heap| {
+        let result = f(heap);
+        (result, heap.inner.trap.take())
+    });
+    if let Some(trap) = trap {
+        trap.raise()
+    }
+    result
+}
+
+/// Moves `value` into memory allocated from the global heap, see [`Bump::alloc_value`].
+pub fn alloc_value<T>(value: T) -> Option<*mut T> {
+    with_global(|heap| heap.alloc_value(value))
+}
+
+/// Allocates `size` bytes aligned to `align` from the global heap, see [`Bump::alloc_aligned`].
+pub fn alloc_aligned(size: usize, align: usize) -> Result<*mut u8, AllocError> {
+    with_global(|heap| heap.alloc_aligned(size, align))
+}
+
+/// Allocates memory filled with `byte` from the global heap, see [`Bump::alloc_filled`].
+pub fn alloc_filled(layout: Layout, byte: u8) -> Option<*mut u8> {
+    with_global(|heap| heap.alloc_filled(layout, byte))
+}
+
+/// Freezes the global heap, see [`Bump::freeze`].
+pub fn freeze() {
+    with_global(|heap| heap.freeze())
+}
+
+/// Thaws the global heap, see [`Bump::thaw`].
+pub fn thaw() {
+    with_global(|heap| heap.thaw())
+}
+
+/// Registers `observer` to be notified about the activity of the global heap.
+#[cfg(all(feature = "observer", not(feature = "std")))]
+pub fn set_observer(observer: &'static dyn AllocObserver) {
+    with_global(|heap| heap.set_observer(observer))
+}
+
+/// Registers `observer` to be notified about the activity of the global heap.
+///
+/// Off-chain the observer is notified by every thread which shares the global heap, see
+/// [`BumpAllocator`].
+#[cfg(all(feature = "observer", feature = "std"))]
+pub fn set_observer(observer: &'static (dyn AllocObserver + Sync)) {
+    with_global(|heap| heap.set_observer(observer))
+}
+
+/// Adopts pages which have been grown outside of the global heap, see [`Bump::resync`].
+pub fn resync() {
+    with_global(|heap| heap.resync())
+}
+
+/// Grows the global heap by exactly `pages` pages, see [`Bump::try_grow_pages`].
+pub fn try_grow_pages(pages: usize) -> Result<usize, AllocError> {
+    with_global(|heap| heap.try_grow_pages(pages))
+}
+
+/// Returns the log of the events of the global heap, see [`Bump::event_log`].
+#[cfg(feature = "debug-log")]
+pub fn event_log() -> EventLog {
+    with_global(|heap| *heap.event_log())
+}
+
+/// Returns the live allocations of the global heap, see [`Bump::memory_map`].
+#[cfg(feature = "debug-tracking")]
+pub fn memory_map() -> Vec<(usize, usize, Option<&'static str>)> {
+    // Listing the allocations allocates, which must not happen while we access the global heap
+    let live = with_global(|heap| heap.inner.live);
+    live.sorted()
+}
+
+/// Returns the statistics of the global heap.
+#[cfg(feature = "stats")]
+pub fn stats() -> AllocStats {
+    with_global(|heap| heap.stats())
+}
+
+/// Resets the statistics of the global heap, e.g. between the runs of a benchmark.
+#[cfg(feature = "stats")]
+pub fn clear_stats() {
+    with_global(|heap| heap.clear_stats())
+}
+
+/// Checks in debug builds that the global heap has not been used yet, see [`Bump::check_init`].
+///
+/// Call this at the entry point of the contract, before anything is allocated.
+#[cfg(feature = "init-check")]
+pub fn check_init() {
+    with_global(|heap| heap.check_init())
+}
+
+/// Limits the number of bytes the global heap hands out from now on, see
+/// [`Bump::set_alloc_budget`].
+#[cfg(feature = "alloc-budget")]
+pub fn set_alloc_budget(bytes: usize) {
+    with_global(|heap| heap.set_alloc_budget(bytes))
+}
+
+/// Forbids or allows the global heap to grow, see [`Bump::set_forbid_grow`].
+#[cfg(feature = "forbid-grow")]
+pub fn set_forbid_grow(forbid: bool) {
+    with_global(|heap| heap.set_forbid_grow(forbid))
+}
+
+/// Allocates memory for the given `Layout` from the global heap and attributes it to `tag`.
+///
+/// Returns a null pointer if there is not enough memory left.
+#[cfg(feature = "debug-tags")]
+pub fn alloc_tagged(layout: Layout, tag: &'static str) -> *mut u8 {
+    match with_global(|heap| heap.alloc_tagged(layout, tag)) {
+        Some(start) => start as *mut u8,
+        None => core::ptr::null_mut(),
+    }
+}
+
+/// A bump allocator suitable for use in a Wasm environment.
+///
+/// Contracts are executed single threaded, so on-chain there is never more than one user of the
+/// global heap at a time. Off-chain, i.e. with the `std` crate feature enabled, the heap lives in
+/// an emulation of the Wasm memory and may be shared by multiple threads, e.g. by tests, so
+/// there every access to it takes a lock.
+pub struct BumpAllocator;
+
+impl BumpAllocator {
+    /// Returns whether `ptr` lies within the global heap, see [`Bump::owns`].
+    pub fn owns(&self, ptr: *mut u8) -> bool {
+        with_global(|heap| heap.owns(ptr))
+    }
+
+    /// Returns whether an allocation of the given `Layout` would currently succeed, see
+    /// [`Bump::can_alloc`].
+    pub fn can_alloc(&self, layout: Layout) -> bool {
+        with_global(|heap| heap.can_alloc(layout))
+    }
+}
+
+unsafe impl GlobalAlloc for BumpAllocator {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        match with_global(|heap| heap.alloc(layout)) {
+            Some(start) => start as *mut u8,
+            None => core::ptr::null_mut(),
+        }
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        match with_global(|heap| heap.alloc_zeroed(layout)) {
+            Some(start) => start as *mut u8,
+            None => core::ptr::null_mut(),
+        }
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        with_global(|heap| heap.dealloc(ptr as usize, layout))
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        match with_global(|heap| heap.realloc(ptr as usize, layout, new_size)) {
+            Some(start) => start as *mut u8,
+            None => core::ptr::null_mut(),
+        }
+    }
+}
+
+/// A bump allocator whose heap is built from the pages of a [`PageSource`].
+///
+/// This is the allocator behind the [`BumpAllocator`], as an ordinary value. Every instance
+/// manages its own heap, so multiple independent arenas can be used side by side.
+///
+/// Allocations are identified by their start address within the memory of the page source.
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct Bump<P> {
+    inner: InnerAlloc<P>,
+}
+
+impl<P> Bump<P>
+where
+    P: PageSource,
+{
+    /// Creates an empty allocator which requests its pages from `pages`.
+    pub const fn new(pages: P) -> Self {
+        Self {
+            inner: InnerAlloc::with_page_source(pages),
+        }
+    }
+
+    /// Creates an empty allocator which requests its pages from `pages` and is tuned by `config`.
+    pub const fn with_config(pages: P, config: Config) -> Self {
+        Self {
+            inner: InnerAlloc::with_config(pages, config),
+        }
+    }
+
+    /// Checks in debug builds that the heap has not been used yet.
+    ///
+    /// This is meant to be called once before the first allocation, e.g. at the entry point of
+    /// a contract, to catch allocations which sneak in before the allocator is set up.
+    ///
+    /// The page arithmetic of the allocator does not need to be checked up front: with the
+    /// `init-check` crate feature enabled, every grow of the heap traps unless the page source
+    /// grew by exactly the requested number of pages of 64KiB each, in release builds as well.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, if the heap has been used before.
+    #[cfg(feature = "init-check")]
+    pub fn check_init(&self) {
+        debug_assert!(
+            self.inner.requested_pages == 0 && self.inner.next == 0,
+            "the allocator has been used before its initialization"
+        );
+    }
+
+    /// Returns whether an allocation of the given `Layout` would currently succeed.
+    ///
+    /// This accounts for the padding required by the alignment of the `Layout`, any overflow of
+    /// the address computations and the maximum number of pages of the allocator, see
+    /// [`Config::max_pages`]. Neither the state of the heap is changed nor any pages are
+    /// requested, so this cannot know whether the page source would actually provide the pages.
+    pub fn can_alloc(&self, layout: Layout) -> bool {
+        self.inner.can_alloc(layout)
+    }
+
+    /// Tries to allocate enough memory for the given `Layout`.
+    ///
+    /// Returns the start of the allocation, or `None` if there is not enough memory left. Empty
+    /// allocations do not take up any memory of the heap, they start at a dangling address
+    /// instead.
+    #[inline]
+    pub fn alloc(&mut self, layout: Layout) -> Option<usize> {
+        self.inner.alloc(layout)
+    }
+
+    /// Tries to allocate enough zeroed memory for the given `Layout`.
+    ///
+    /// Returns the start of the allocation, or `None` if there is not enough memory left.
+    #[inline]
+    pub fn alloc_zeroed(&mut self, layout: Layout) -> Option<usize> {
+        self.inner.alloc_zeroed(layout)
+    }
+
+    /// Tries to allocate enough memory for an array of `n` values of type `T`.
+    ///
+    /// Returns the start of the allocation together with the `Layout` of the array, or
+    /// [`AllocError::SizeOverflow`] if the size of the array overflows and
+    /// [`AllocError::OutOfMemory`] if there is not enough memory left.
+    #[cfg(feature = "std")]
+    pub fn alloc_array<T>(&mut self, n: usize) -> Result<(usize, Layout), AllocError> {
+        self.inner.alloc_array::<T>(n)
+    }
+
+    /// Allocates memory for all of the given `Layout`s in one go, e.g. for tools which plan
+    /// their allocations ahead.
+    ///
+    /// Returns the starts of the allocations in the order of their `Layout`s. If any of them
+    /// does not fit none of them is allocated and `None` is returned, leaving the allocator as
+    /// it was. The batch is planned ahead, so no pages are requested for a batch which does not
+    /// fit. Only if the page source refuses to grow are the allocations made so far released
+    /// again, in which case the pages it did grant stay reserved for the allocator.
+    #[cfg(feature = "std")]
+    pub fn bulk_alloc(&mut self, layouts: &[Layout]) -> Option<Vec<usize>> {
+        self.inner.bulk_alloc(layouts)
+    }
+
+    /// Moves `value` into newly allocated memory.
+    ///
+    /// Returns a pointer to the value, or `None` if there is not enough memory left. The value
+    /// is never dropped by the allocator, it is up to the caller to do so if needed.
+    pub fn alloc_value<T>(&mut self, value: T) -> Option<*mut T> {
+        self.inner.alloc_value(value)
+    }
+
+    /// Allocates `size` bytes aligned to `align`, e.g. as scratch space for cryptographic
+    /// routines which require 32 or 64 byte aligned buffers.
+    ///
+    /// The returned pointer is guaranteed to be a multiple of `align`, regardless of the
+    /// allocations which happened before. Returns [`AllocError::InvalidAlign`] if `align` is not
+    /// a power of two, [`AllocError::SizeOverflow`] if the size overflows when rounded up to
+    /// `align` and [`AllocError::OutOfMemory`] if there is not enough memory left.
+    pub fn alloc_aligned(
+        &mut self,
+        size: usize,
+        align: usize,
+    ) -> Result<*mut u8, AllocError> {
+        let layout = layout(size, align)?;
+        self.alloc(layout)
+            .map(|start| start as *mut u8)
+            .ok_or(AllocError::OutOfMemory)
+    }
+
+    /// Allocates enough memory for the given `Layout` with every byte set to `byte`, e.g. `0xFF`
+    /// for a bitmap of set bits.
+    ///
+    /// Filling with `0` is as cheap as [`Bump::alloc_zeroed`], which skips the bytes of fresh
+    /// pages. Returns `None` if there is not enough memory left.
+    pub fn alloc_filled(&mut self, layout: Layout, byte: u8) -> Option<*mut u8> {
+        self.inner.alloc_filled(layout, byte)
+    }
+
+    /// Tries to allocate enough memory for the given `Layout` and attributes it to `tag`.
+    ///
+    /// The number of bytes handed out per tag is reported by [`Bump::stats`].
+    #[cfg(feature = "debug-tags")]
+    pub fn alloc_tagged(&mut self, layout: Layout, tag: &'static str) -> Option<usize> {
+        self.inner.alloc_tagged(layout, tag)
+    }
+
+    /// Releases the allocation starting at `start`.
+    ///
+    /// By default this is a no-op: a bump allocator only ever releases memory all at once, see
+    /// [`Bump::reset`]. With the `free-list` crate feature enabled the released memory is handed
+    /// out again by subsequent allocations which fit into it.
+    ///
+    /// Without such a reclaiming feature the heap is never touched by a deallocation, which keeps
+    /// the allocator as small as possible. Any change to this is a breaking change for contracts
+    /// relying on the footprint of the allocator.
+    #[inline]
+    pub fn dealloc(&mut self, start: usize, layout: Layout) {
+        self.inner.dealloc(start, layout)
+    }
+
+    /// Resizes the allocation starting at `start` to `new_size` bytes.
+    ///
+    /// Shrinking always happens in place. With the `free-list` crate feature enabled the bytes
+    /// cut off are handed out again by subsequent allocations, otherwise they are lost.
+    ///
+    /// Growing the last allocation on the heap happens in place as well, requesting more pages
+    /// if needed. Any other allocation is moved to a new place and its contents are copied over.
+    ///
+    /// Returns the new start of the allocation, or `None` if there is not enough memory left.
+    /// The allocation is left untouched in that case.
+    ///
+    /// # Safety
+    ///
+    /// `start` must be the start of a live allocation of this allocator with the given `layout`.
+    pub unsafe fn realloc(
+        &mut self,
+        start: usize,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<usize> {
+        self.inner.realloc(start, layout, new_size)
+    }
+
+    /// Returns whether `ptr` lies within the heap of this allocator.
+    ///
+    /// This allows composite allocators to route deallocations to the allocator they came from,
+    /// and debug builds to assert that only memory of the heap is released. Note that this is
+    /// about the memory reserved for the heap, `ptr` does not need to be part of a live
+    /// allocation.
+    pub fn owns(&self, ptr: *mut u8) -> bool {
+        (self.inner.start..self.inner.upper_limit).contains(&(ptr as usize))
+    }
+
+    /// Freezes the heap, so that all allocations fail until it is thawed again.
+    ///
+    /// This allows embedders to forbid allocations during sensitive windows, e.g. while a
+    /// cross-contract call might re-enter the contract. Depending on the configuration an
+    /// allocation while frozen either returns `None` or traps, see [`Config::trap_when_frozen`].
+    ///
+    /// Freezing nests: the heap is only thawed once [`Bump::thaw`] has been called as often as
+    /// [`Bump::freeze`].
+    pub fn freeze(&mut self) {
+        self.inner.frozen += 1;
+    }
+
+    /// Undoes one call to [`Bump::freeze`].
+    ///
+    /// Does nothing if the heap is not frozen.
+    pub fn thaw(&mut self) {
+        self.inner.frozen = self.inner.frozen.saturating_sub(1);
+    }
+
+    /// Returns whether the heap is currently frozen.
+    pub fn is_frozen(&self) -> bool {
+        self.inner.frozen != 0
+    }
+
+    /// Brings the heap back in sync with the memory of the page source after it has been grown
+    /// by someone else, e.g. by the host.
+    ///
+    /// All pages from the end of the heap up to the current size of the memory are claimed for
+    /// the heap, so this must only be called if nobody else is using those pages. Just like the
+    /// pages we request ourselves they are assumed to be zero initialized and count towards the
+    /// maximum number of pages, see [`Config::max_pages`]; pages beyond it are left alone. The
+    /// heap never shrinks, and allocations which have been handed out already are not affected.
+    pub fn resync(&mut self) {
+        self.inner.resync()
+    }
+
+    /// Grows the heap by exactly `pages` pages without allocating anything.
+    ///
+    /// This allows embedders to reserve memory up front, e.g. for capacity planning. Just like
+    /// for an allocation which does not fit, new pages which are not adjacent to the heap
+    /// replace it.
+    ///
+    /// Returns the new end of the heap, or [`AllocError::OutOfMemory`] if the pages are not
+    /// available, would exceed the maximum number of pages or their end cannot be represented.
+    ///
+    /// # Panics
+    ///
+    /// If growing the heap has been forbidden with the `forbid-grow` crate feature.
+    pub fn try_grow_pages(&mut self, pages: usize) -> Result<usize, AllocError> {
+        self.inner
+            .try_grow_pages(pages)
+            .ok_or(AllocError::OutOfMemory)
+    }
+
+    /// Returns whether no memory of the heap is handed out, e.g. after a [`Bump::reset`].
+    ///
+    /// Deallocations never shrink the heap, so a heap whose allocations have all been released
+    /// one by one is still not empty.
+    pub fn is_empty(&self) -> bool {
+        self.inner.next == self.inner.start
+    }
+
+    /// Releases all allocations at once.
+    ///
+    /// The pages which have been requested so far stay reserved for the allocator and are reused
+    /// by subsequent allocations.
+    pub fn reset(&mut self) {
+        self.inner.next = self.inner.start;
+        #[cfg(feature = "free-list")]
+        self.inner.free.clear();
+        #[cfg(feature = "size-classes")]
+        self.inner.classes.clear();
+        #[cfg(feature = "debug-tracking")]
+        self.inner.live.clear();
+    }
+
+    /// Opens a scope whose allocations are all released at once when it is dropped.
+    ///
+    /// The scope allocates from the heap of this allocator, which cannot be used until the scope
+    /// is dropped. Pages requested within the scope stay reserved for the allocator afterwards.
+    pub fn scope(&mut self) -> BumpScope<'_, P> {
+        BumpScope {
+            checkpoint: self.inner.checkpoint(),
+            parent: self,
+        }
+    }
+
+    /// Registers `observer` to be notified about the activity of the allocator.
+    ///
+    /// Replaces any previously registered observer.
+    #[cfg(feature = "observer")]
+    pub fn set_observer(&mut self, observer: &'static dyn AllocObserver) {
+        self.inner.observer = Some(observer);
+    }
+
+    /// Limits the number of bytes the allocator hands out from now on to `bytes`, replacing any
+    /// previous budget.
+    ///
+    /// Every successful allocation is charged against the budget with its size including any
+    /// padding, and allocations which exceed the rest of the budget fail as if the memory was
+    /// exhausted, regardless of how many pages are left. Releasing memory does not refund the
+    /// budget, so it limits the memory allocated over the whole lifetime of a contract.
+    #[cfg(feature = "alloc-budget")]
+    pub fn set_alloc_budget(&mut self, bytes: usize) {
+        self.inner.budget = Some(bytes);
+    }
+
+    /// Returns the number of bytes which can still be allocated within the budget, or `None` if
+    /// no budget has been set, see [`Bump::set_alloc_budget`].
+    #[cfg(feature = "alloc-budget")]
+    pub fn alloc_budget(&self) -> Option<usize> {
+        self.inner.budget
+    }
+
+    /// Forbids or allows the heap to grow.
+    ///
+    /// While growing is forbidden every attempt to request new pages panics, which traps on
+    /// Wasm. This allows tests to assert that a code path is served entirely from the pages
+    /// which have been requested before.
+    #[cfg(feature = "forbid-grow")]
+    pub fn set_forbid_grow(&mut self, forbid: bool) {
+        self.inner.forbid_grow = forbid;
+    }
+
+    /// Returns the statistics of the allocator.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> AllocStats {
+        self.inner.stats
+    }
+
+    /// Resets all statistics of the allocator to zero.
+    ///
+    /// The state of the heap itself is left untouched.
+    #[cfg(feature = "stats")]
+    pub fn clear_stats(&mut self) {
+        self.inner.stats = AllocStats::new();
+        #[cfg(feature = "export-metrics")]
+        self.inner.export_metrics();
+    }
+
+    /// Defers traps, e.g. when growing is forbidden, until the heap is released.
+    ///
+    /// Instead of panicking right away the failing operation just fails, and the trap is kept
+    /// until `with_global` raises it. Only the global heap does so, since the panic machinery
+    /// might need to allocate from it.
+    pub(crate) const fn deferring_traps(mut self) -> Self {
+        self.inner.defer_traps = true;
+        self
+    }
+
+    /// Publishes the statistics of this heap to the exported globals, e.g.
+    /// [`INK_HEAP_ALLOCATIONS`](crate::INK_HEAP_ALLOCATIONS), whenever they change.
+    ///
+    /// Only the global heap does so, since there is only a single set of globals.
+    #[cfg(feature = "export-metrics")]
+    pub(crate) const fn exporting_metrics(mut self) -> Self {
+        self.inner.exported = true;
+        self
+    }
+
+    /// Returns the log of the events which changed the state of the heap.
+    ///
+    /// Allocations refused while the heap is frozen are not logged, and neither are rewinds of
+    /// the heap, e.g. by [`Bump::reset`].
+    #[cfg(feature = "debug-log")]
+    pub fn event_log(&self) -> &EventLog {
+        &self.inner.log
+    }
+
+    /// Returns the allocations which have not been released yet, ordered by their address.
+    ///
+    /// Every allocation is listed with its start, its size as requested and the tag it has been
+    /// allocated with, if any. Tooling can render this as a map of the heap to spot leaks or
+    /// unexpectedly large allocations.
+    ///
+    /// At most 256 allocations are tracked at the same time, any further ones are only counted,
+    /// see [`Bump::untracked_allocations`].
+    #[cfg(feature = "debug-tracking")]
+    pub fn memory_map(&self) -> Vec<(usize, usize, Option<&'static str>)> {
+        self.inner.live.sorted()
+    }
+
+    /// Returns the number of allocations missing from the [`Bump::memory_map`] since the
+    /// tracking table was full when they were made.
+    #[cfg(feature = "debug-tracking")]
+    pub fn untracked_allocations(&self) -> usize {
+        self.inner.live.untracked()
+    }
+}
+
+/// A scope of a [`Bump`] whose allocations are all released at once when it is dropped.
+///
+/// Created by [`Bump::scope`]. The scope dereferences to its parent allocator, so all of its
+/// methods can be used to allocate within the scope, including opening nested scopes.
+#[cfg_attr(feature = "std", derive(Debug))]
+pub struct BumpScope<'a, P>
+where
+    P: PageSource,
+{
+    parent: &'a mut Bump<P>,
+    /// The state of the heap of the parent when the scope was opened.
+    checkpoint: Checkpoint,
+}
+
+impl<'a, P> core::ops::Deref for BumpScope<'a, P>
+where
+    P: PageSource,
+{
+    type Target = Bump<P>;
+
+    fn deref(&self) -> &Self::Target {
+        self.parent
+    }
+}
+
+impl<'a, P> core::ops::DerefMut for BumpScope<'a, P>
+where
+    P: PageSource,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.parent
+    }
+}
+
+impl<'a, P> Drop for BumpScope<'a, P>
+where
+    P: PageSource,
+{
+    fn drop(&mut self) {
+        self.parent.inner.rewind(self.checkpoint)
+    }
+}
+
+/// A source of Wasm pages from which the heap is built.
+///
+/// This mirrors the `memory.grow` instruction of the Wasm engine and allows us to swap in other
+/// implementations of it, e.g. for testing.
+pub trait PageSource {
+    /// Grows the memory by `pages` pages, each of which is `64KiB` in size.
+    ///
+    /// Returns the previous size of the memory in pages, or `None` if the memory could not be
+    /// grown.
+    fn memory_grow(&mut self, pages: usize) -> Option<usize>;
+
+    /// Returns the current size of the memory in pages.
+    fn memory_size(&self) -> usize;
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(test)] {
+        /// Emulates the memory of a Wasm engine which starts out with no pages at all.
+        ///
+        /// This implementation is only meant to be used for testing, since we cannot (easily)
+        /// test the `wasm32` implementation. None of the pages are actually backed by memory.
+        #[derive(Debug, Copy, Clone)]
+        pub struct Pages {
+            /// The current size of the emulated memory in pages.
+            size: usize,
+        }
+
+        impl Pages {
+            pub const fn new() -> Self {
+                Self::with_base(0)
+            }
+
+            /// Creates an emulated memory whose first new page starts at `base`, as if the
+            /// memory below it was taken by the data of the contract.
+            ///
+            /// This allows tests to pin the absolute addresses of the heap. `base` must be a
+            /// multiple of the page size.
+            pub const fn with_base(base: usize) -> Self {
+                assert!(base.is_multiple_of(PAGE_SIZE), "the base is not page aligned");
+                Self {
+                    size: base / PAGE_SIZE,
+                }
+            }
+        }
+
+        impl PageSource for Pages {
+            fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+                let prev_size = self.size;
+                self.size = prev_size.checked_add(pages)?;
+                Some(prev_size)
+            }
+
+            fn memory_size(&self) -> usize {
+                self.size
+            }
+        }
+    } else if #[cfg(feature = "std")] {
+        use crate::emulated::EmulatedHeap;
+
+        /// The maximum number of pages of the emulated memory of the global heap (`64MiB`).
+        const MAX_EMULATED_PAGES: usize = 1024;
+
+        /// Off-chain the global heap lives in an emulation of the Wasm memory.
+        #[derive(Debug)]
+        pub struct Pages(EmulatedHeap);
+
+        impl Pages {
+            pub const fn new() -> Self {
+                Self(EmulatedHeap::new(MAX_EMULATED_PAGES))
+            }
+        }
+
+        impl PageSource for Pages {
+            fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+                self.0.memory_grow(pages)
+            }
+
+            fn memory_size(&self) -> usize {
+                self.0.memory_size()
+            }
+        }
+    } else if #[cfg(target_arch = "wasm32")] {
+        /// The memory of the Wasm engine executing the contract.
+        pub struct Pages;
+
+        impl Pages {
+            pub const fn new() -> Self {
+                Self
+            }
+        }
+
+        impl PageSource for Pages {
+            fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+                let prev_page = core::arch::wasm32::memory_grow(0, pages);
+                if prev_page == usize::MAX {
+                    return None;
+                }
+
+                Some(prev_page)
+            }
+
+            fn memory_size(&self) -> usize {
+                core::arch::wasm32::memory_size(0)
+            }
+        }
+    } else {
+        compile_error! {
+            "ink! only supports compilation as `std` or `no_std` + `wasm32-unknown`"
+        }
+    }
+}
+
+/// Tunable parameters of the bump allocator.
+///
+/// All parameters can be set in `const` contexts, e.g. when initializing a `static`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Config {
+    /// The size of every allocation is rounded up to a multiple of this.
+    granularity: usize,
+    /// Every allocation is aligned to at least this.
+    min_align: usize,
+    /// The maximum number of pages the allocator requests in total, if any.
+    max_pages: Option<usize>,
+    /// The minimum number of pages the heap grows by at once.
+    grow_increment: usize,
+    /// Whether allocating from a frozen heap traps instead of failing.
+    trap_when_frozen: bool,
+    /// The highest address the heap may use.
+    ///
+    /// Every address computation of the heap is checked against it, so that tests can restrict
+    /// the heap to the 32-bit addresses of Wasm, see [`Config::addr32`]. By default the heap may
+    /// use the whole address space of the target, which in Wasm is 32 bits wide already.
+    max_addr: usize,
+}
+
+impl Config {
+    /// The configuration of the global allocator.
+    ///
+    /// Every allocation is aligned to at least the size of a word, which many users assume even
+    /// when asking for a smaller alignment.
+    pub const DEFAULT: Self = Self {
+        granularity: 1,
+        min_align: core::mem::align_of::<usize>(),
+        max_pages: None,
+        grow_increment: 1,
+        trap_when_frozen: false,
+        max_addr: usize::MAX,
+    };
+
+    /// Rounds the size of every allocation up to a multiple of `granularity` bytes.
+    ///
+    /// This keeps allocations at a consistent alignment, at the cost of some memory for tiny
+    /// allocations.
+    ///
+    /// # Panics
+    ///
+    /// If `granularity` is not a power of two.
+    pub const fn granularity(self, granularity: usize) -> Self {
+        assert!(
+            granularity.is_power_of_two(),
+            "the allocation granularity must be a power of two"
+        );
+        Self {
+            granularity,
+            ..self
+        }
+    }
+
+    /// Aligns every allocation to at least `min_align` bytes.
+    ///
+    /// The size of every allocation is rounded up to a multiple of `min_align` as well, so that
+    /// the start of the next allocation stays aligned too. A `min_align` of `1` hands out
+    /// exactly the bytes requested.
+    ///
+    /// # Panics
+    ///
+    /// If `min_align` is not a power of two.
+    pub const fn min_align(self, min_align: usize) -> Self {
+        assert!(
+            min_align.is_power_of_two(),
+            "the minimum alignment must be a power of two"
+        );
+        Self { min_align, ..self }
+    }
+
+    /// Never requests more than `max_pages` pages in total.
+    ///
+    /// Once the pages are used up every allocation which does not fit into the heap fails, even
+    /// if the page source could provide more pages.
+    pub const fn max_pages(self, max_pages: usize) -> Self {
+        Self {
+            max_pages: Some(max_pages),
+            ..self
+        }
+    }
+
+    /// Grows the heap by at least `grow_increment` pages at once.
+    ///
+    /// This saves calls to the page source for steadily growing heaps. Allocations which need
+    /// more pages still get all of them, and the maximum number of pages is never exceeded for
+    /// the sake of the increment.
+    ///
+    /// # Panics
+    ///
+    /// If `grow_increment` is zero.
+    pub const fn grow_increment(self, grow_increment: usize) -> Self {
+        assert!(grow_increment != 0, "the grow increment must not be zero");
+        Self {
+            grow_increment,
+            ..self
+        }
+    }
+
+    /// Traps on allocations while the heap is frozen, instead of letting them fail.
+    ///
+    /// See [`Bump::freeze`].
+    pub const fn trap_when_frozen(self) -> Self {
+        Self {
+            trap_when_frozen: true,
+            ..self
+        }
+    }
+
+    /// Restricts the heap to 32-bit addresses, as used by Wasm.
+    ///
+    /// This is what the allocator does on-chain anyway. Off-chain it allows tests to catch
+    /// overflows which only happen on-chain, as long as their page source hands out pages at
+    /// low enough addresses.
+    pub const fn addr32(self) -> Self {
+        Self {
+            max_addr: u32::MAX as usize,
+            ..self
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The state of a heap to which it can be rewound, see [`InnerAlloc::rewind`].
+#[derive(Debug, Copy, Clone)]
+struct Checkpoint {
+    /// The start of the heap.
+    start: usize,
+    /// The next allocation on the heap.
+    next: usize,
+    /// The spans of the heap which were free.
+    #[cfg(feature = "free-list")]
+    free: FreeList,
+    /// The blocks of the heap which were free, binned by their size class.
+    #[cfg(feature = "size-classes")]
+    classes: SizeClasses,
+}
+
+/// A page source which grants every grow without any memory behind it, to plan allocations
+/// ahead, see [`InnerAlloc::fits_all`].
+#[cfg(feature = "std")]
+struct PlannedPages(usize);
+
+#[cfg(feature = "std")]
+impl PageSource for PlannedPages {
+    fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+        let prev_size = self.0;
+        self.0 = prev_size.checked_add(pages)?;
+        Some(prev_size)
+    }
+
+    fn memory_size(&self) -> usize {
+        self.0
+    }
+}
+
+/// A misuse of the heap which aborts the contract, see [`InnerAlloc::trap`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Trap {
+    /// Tried to request `pages` new pages while growing is forbidden.
+    #[cfg(feature = "forbid-grow")]
+    Grow { pages: usize },
+    /// Tried to allocate while the heap is frozen, see [`Config::trap_when_frozen`].
+    Frozen,
+    /// The page source grew by pages of another size than ours, see [`Bump::check_init`].
+    #[cfg(feature = "init-check")]
+    PageSize,
+}
+
+impl Trap {
+    /// Panics with a description of the trap, which traps on Wasm.
+    fn raise(self) -> ! {
+        match self {
+            #[cfg(feature = "forbid-grow")]
+            Trap::Grow { pages } => {
+                panic!(
+                    "tried to grow the heap by {} pages while growing is forbidden",
+                    pages
+                )
+            }
+            Trap::Frozen => panic!("tried to allocate while the heap is frozen"),
+            #[cfg(feature = "init-check")]
+            Trap::PageSize => {
+                panic!("the pages of the memory are not {} bytes large", PAGE_SIZE)
+            }
+        }
+    }
+}
+
+#[cfg_attr(feature = "std", derive(Copy, Clone))]
+struct InnerAlloc<P> {
+    /// The address of the start of our heap.
+    ///
+    /// Every byte from here up to `upper_limit` belongs to us.
+    start: usize,
+
+    /// Points to the start of the next available allocation.
+    next: usize,
+
+    /// The address of the upper limit of our heap.
+    upper_limit: usize,
+
+    /// Every byte at or above this address has never been handed out, and thus is still zero
+    /// initialized.
+    untouched: usize,
+
+    /// The number of pages we have requested so far.
+    requested_pages: usize,
+
+    /// How many times the heap has been frozen without being thawed again.
+    frozen: u32,
+
+    /// Where we get new pages of memory from once the heap is exhausted.
+    pages: P,
+
+    /// The parameters the allocator was set up with.
+    config: Config,
+
+    /// The spans of the heap which have been freed and can be handed out again.
+    #[cfg(feature = "free-list")]
+    free: FreeList,
+
+    /// The blocks of the heap which have been freed, binned by their size class.
+    #[cfg(feature = "size-classes")]
+    classes: SizeClasses,
+
+    /// Whether requesting new pages panics instead.
+    #[cfg(feature = "forbid-grow")]
+    forbid_grow: bool,
+
+    /// Whether traps are deferred, see [`Bump::deferring_traps`].
+    defer_traps: bool,
+
+    /// The first trap which has been deferred and not been raised yet.
+    trap: Option<Trap>,
+
+    /// The number of bytes which may still be allocated, if limited.
+    #[cfg(feature = "alloc-budget")]
+    budget: Option<usize>,
+
+    /// Gets notified about the activity of the allocator.
+    #[cfg(feature = "observer")]
+    observer: Option<&'static dyn AllocObserver>,
+
+    /// The events which changed the state of the heap.
+    #[cfg(feature = "debug-log")]
+    log: EventLog,
+
+    #[cfg(feature = "debug-tracking")]
+    live: LiveAllocations,
+
+    #[cfg(feature = "stats")]
+    stats: AllocStats,
+
+    /// Whether the statistics are published, see [`Bump::exporting_metrics`].
+    #[cfg(feature = "export-metrics")]
+    exported: bool,
+}
+
+#[cfg(feature = "std")]
+impl<P> core::fmt::Debug for InnerAlloc<P> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        let reserved = self.upper_limit - self.start;
+        let pages = reserved / PAGE_SIZE;
+        f.debug_struct("InnerAlloc")
+            .field("used", &format_args!("{}", Bytes(self.next - self.start)))
+            .field(
+                "reserved",
+                &format_args!(
+                    "{} ({} {})",
+                    Bytes(reserved),
+                    pages,
+                    if pages == 1 { "page" } else { "pages" }
+                ),
+            )
+            .field("next", &format_args!("{:#x}", self.next))
+            .finish()
+    }
+}
+
+/// Displays a number of bytes in the largest binary unit which represents it exactly.
+#[cfg(feature = "std")]
+struct Bytes(usize);
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for Bytes {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        const KIB: usize = 1024;
+        const MIB: usize = 1024 * KIB;
+        match self.0 {
+            0 => write!(f, "0B"),
+            bytes if bytes % MIB == 0 => write!(f, "{}MiB", bytes / MIB),
+            bytes if bytes % KIB == 0 => write!(f, "{}KiB", bytes / KIB),
+            bytes => write!(f, "{}B", bytes),
+        }
+    }
+}
+
+impl InnerAlloc<Pages> {
+    #[cfg(test)]
+    const fn new() -> Self {
+        Self::with_page_source(Pages::new())
+    }
+
+    /// Creates an allocator whose heap begins at `base`, see [`Pages::with_base`].
+    #[cfg(test)]
+    const fn with_base(base: usize) -> Self {
+        Self::with_page_source(Pages::with_base(base))
+    }
+
+    /// Creates an allocator whose heap is in the given state.
+    ///
+    /// This allows tests to put the allocator into states which would otherwise require a huge
+    /// number of allocations to reach, e.g. close to the end of the address space.
+    #[cfg(test)]
+    fn from_parts(next: usize, upper_limit: usize) -> Self {
+        Self {
+            start: 0,
+            next,
+            upper_limit,
+            untouched: next,
+            requested_pages: upper_limit / PAGE_SIZE,
+            frozen: 0,
+            pages: Pages {
+                size: upper_limit / PAGE_SIZE,
+            },
+            config: Config::DEFAULT,
+            #[cfg(feature = "free-list")]
+            free: FreeList::new(),
+            #[cfg(feature = "size-classes")]
+            classes: SizeClasses::new(),
+            #[cfg(feature = "forbid-grow")]
+            forbid_grow: false,
+            defer_traps: false,
+            trap: None,
+            #[cfg(feature = "alloc-budget")]
+            budget: None,
+            #[cfg(feature = "observer")]
+            observer: None,
+            #[cfg(feature = "debug-log")]
+            log: EventLog::new(),
+            #[cfg(feature = "debug-tracking")]
+            live: LiveAllocations::new(),
+            #[cfg(feature = "stats")]
+            stats: AllocStats::new(),
+            #[cfg(feature = "export-metrics")]
+            exported: false,
+        }
+    }
+}
+
+impl<P> InnerAlloc<P>
+where
+    P: PageSource,
+{
+    const fn with_page_source(pages: P) -> Self {
+        Self::with_config(pages, Config::DEFAULT)
+    }
+
+    const fn with_config(pages: P, config: Config) -> Self {
+        Self {
+            start: 0,
+            next: 0,
+            upper_limit: 0,
+            untouched: 0,
+            requested_pages: 0,
+            frozen: 0,
+            pages,
+            config,
+            #[cfg(feature = "free-list")]
+            free: FreeList::new(),
+            #[cfg(feature = "size-classes")]
+            classes: SizeClasses::new(),
+            #[cfg(feature = "forbid-grow")]
+            forbid_grow: false,
+            defer_traps: false,
+            trap: None,
+            #[cfg(feature = "alloc-budget")]
+            budget: None,
+            #[cfg(feature = "observer")]
+            observer: None,
+            #[cfg(feature = "debug-log")]
+            log: EventLog::new(),
+            #[cfg(feature = "debug-tracking")]
+            live: LiveAllocations::new(),
+            #[cfg(feature = "stats")]
+            stats: AllocStats::new(),
+            #[cfg(feature = "export-metrics")]
+            exported: false,
+        }
+    }
+
+    /// Raises `trap` right away, or keeps it until the heap is released if traps are deferred,
+    /// see [`Bump::deferring_traps`].
+    ///
+    /// Only the first deferred trap is kept, since the operation it aborts might run into the
+    /// same trap again while falling back to another strategy, e.g. within `realloc`.
+    fn trap(&mut self, trap: Trap) {
+        if !self.defer_traps {
+            trap.raise()
+        }
+        self.trap.get_or_insert(trap);
+    }
+
+    /// Request a `pages` number of pages of Wasm memory. Each page is `64KiB` in size.
+    ///
+    /// Returns the address of the first requested page, or `None` if the pages are not available,
+    /// would exceed the maximum number of pages or their address cannot be represented.
+    ///
+    /// # Panics
+    ///
+    /// If growing the heap has been forbidden, see [`Bump::set_forbid_grow`], unless traps are
+    /// deferred, see [`InnerAlloc::trap`].
+    fn request_pages(&mut self, pages: usize) -> Option<usize> {
+        let page_start = self.try_request_pages(pages);
+        #[cfg(feature = "debug-log")]
+        self.log.record(AllocEvent::Grow {
+            pages,
+            granted: page_start.is_some(),
+        });
+        page_start
+    }
+
+    fn try_request_pages(&mut self, pages: usize) -> Option<usize> {
+        #[cfg(feature = "forbid-grow")]
+        if self.forbid_grow {
+            self.trap(Trap::Grow { pages });
+            return None
+        }
+        if !self.within_max_pages(pages)? {
+            return None
+        }
+
+        let prev_page = self.pages.memory_grow(pages)?;
+        self.requested_pages += pages;
+        #[cfg(feature = "init-check")]
+        if self.pages.memory_size().checked_sub(prev_page) != Some(pages) {
+            self.trap(Trap::PageSize);
+            return None
+        }
+        let page_start = self.page_addr(prev_page)?;
+
+        #[cfg(feature = "observer")]
+        if let Some(observer) = self.observer {
+            observer.on_grow(page_start, pages);
+        }
+
+        Some(page_start)
+    }
+
+    /// Returns the address `offset` bytes after `addr`.
+    ///
+    /// Returns `None` if the address lies beyond the highest address of the heap.
+    #[inline]
+    fn offset_addr(&self, addr: usize, offset: usize) -> Option<usize> {
+        addr.checked_add(offset)
+            .filter(|&addr| addr <= self.config.max_addr)
+    }
+
+    /// Returns the address of the end of `pages` pages starting at `page_start`.
+    ///
+    /// Returns `None` if the address lies beyond the highest address of the heap.
+    #[inline]
+    fn page_end(&self, page_start: usize, pages: usize) -> Option<usize> {
+        pages
+            .checked_mul(PAGE_SIZE)
+            .and_then(|bytes| self.offset_addr(page_start, bytes))
+    }
+
+    /// Returns the address of the start of the Wasm page with the index `page`.
+    ///
+    /// Returns `None` if the address lies beyond the highest address of the heap.
+    #[inline]
+    fn page_addr(&self, page: usize) -> Option<usize> {
+        page.checked_mul(PAGE_SIZE)
+            .filter(|&addr| addr <= self.config.max_addr)
+    }
+
+    /// Returns the number of pages to grow the heap by for an allocation which requires
+    /// `required_pages` pages.
+    ///
+    /// This is at least the configured grow increment, as long as that stays within the maximum
+    /// number of pages, but never less than `required_pages`.
+    #[inline]
+    fn grow_pages(&self, required_pages: usize) -> usize {
+        let pages = core::cmp::max(required_pages, self.config.grow_increment);
+        match self.config.max_pages {
+            Some(max_pages) => {
+                let remaining = max_pages.saturating_sub(self.requested_pages);
+                core::cmp::max(required_pages, core::cmp::min(pages, remaining))
+            }
+            None => pages,
+        }
+    }
+
+    /// Returns the number of pages to grow the heap by for an allocation of `size` bytes aligned
+    /// to `align`, whose new pages start at `page_start`.
+    ///
+    /// New pages always start at a page boundary, so only alignments beyond the page size require
+    /// us to skip some bytes at the start of them, or the reserved first word of the memory.
+    #[inline]
+    fn grow_pages_for(
+        &self,
+        page_start: usize,
+        size: usize,
+        align: usize,
+    ) -> Option<usize> {
+        let padding = core::cmp::max(
+            align.saturating_sub(PAGE_SIZE),
+            align_up(non_null(page_start), align)? - page_start,
+        );
+        Some(self.grow_pages(required_pages(size.checked_add(padding)?)?))
+    }
+
+    /// Returns whether requesting `pages` more pages stays within the maximum number of pages.
+    ///
+    /// A heap which ends up with exactly the maximum number of pages is still within it, so an
+    /// allocation of the whole capacity succeeds on a fresh heap.
+    #[inline]
+    fn within_max_pages(&self, pages: usize) -> Option<bool> {
+        match self.config.max_pages {
+            Some(max_pages) => {
+                Some(self.requested_pages.checked_add(pages)? <= max_pages)
+            }
+            None => Some(true),
+        }
+    }
+
+    /// Returns whether `bytes` more bytes may be allocated, see [`Bump::set_alloc_budget`].
+    #[cfg(feature = "alloc-budget")]
+    #[inline]
+    fn within_budget(&self, bytes: usize) -> bool {
+        self.budget.is_none_or(|budget| bytes <= budget)
+    }
+
+    /// Charges `bytes` allocated bytes against the budget, which must cover them.
+    #[cfg(feature = "alloc-budget")]
+    #[inline]
+    fn charge_budget(&mut self, bytes: usize) {
+        if let Some(budget) = &mut self.budget {
+            *budget -= bytes;
+        }
+    }
+
+    /// Returns whether an allocation of the given `Layout` would currently succeed, see
+    /// [`Bump::can_alloc`].
+    ///
+    /// This mirrors [`InnerAlloc::try_bump`] without changing any state.
+    fn can_alloc(&self, layout: Layout) -> bool {
+        self.try_can_alloc(layout).is_some()
+    }
+
+    fn try_can_alloc(&self, layout: Layout) -> Option<()> {
+        if self.frozen != 0 {
+            return None
+        }
+
+        let aligned_size = self.allocation_size(layout)?;
+        let align = self.alignment(layout);
+
+        if self.dangling(layout).is_some() {
+            return Some(())
+        }
+
+        #[cfg(feature = "alloc-budget")]
+        if !self.within_budget(aligned_size) {
+            return None
+        }
+
+        #[cfg(feature = "size-classes")]
+        if self.classes.fits(aligned_size, align) {
+            return Some(())
+        }
+
+        #[cfg(feature = "free-list")]
+        if self.free.fits(aligned_size, align) {
+            return Some(())
+        }
+
+        let alloc_end =
+            self.offset_addr(align_up(non_null(self.next), align)?, aligned_size)?;
+        if alloc_end <= self.upper_limit {
+            return Some(())
+        }
+
+        #[cfg(feature = "forbid-grow")]
+        if self.forbid_grow {
+            return None
+        }
+
+        // The page source hands out the pages right after its current memory
+        let page_start = self.page_addr(self.pages.memory_size())?;
+        let grow_pages = self.grow_pages_for(page_start, aligned_size, align)?;
+        self.offset_addr(align_up(non_null(page_start), align)?, aligned_size)?;
+        self.page_end(page_start, grow_pages)?;
+        if !self.within_max_pages(grow_pages)? {
+            return None
+        }
+
+        Some(())
+    }
+
+    /// Allocates all of the given `Layout`s or none of them, see [`Bump::bulk_alloc`].
+    #[cfg(feature = "std")]
+    fn bulk_alloc(&mut self, layouts: &[Layout]) -> Option<Vec<usize>> {
+        if !self.fits_all(layouts) {
+            return None
+        }
+
+        let checkpoint = self.checkpoint();
+        let untouched = self.untouched;
+        #[cfg(feature = "alloc-budget")]
+        let budget = self.budget;
+        #[cfg(feature = "stats")]
+        let (stats, upper_limit) = (self.stats, self.upper_limit);
+        #[cfg(feature = "debug-log")]
+        let log = self.log;
+
+        let starts = layouts
+            .iter()
+            .map(|&layout| self.alloc(layout))
+            .collect::<Option<Vec<_>>>();
+        if starts.is_none() {
+            // The page source refused to grow, but the pages it granted before are ours, so we
+            // keep them and undo everything else.
+            self.rewind(checkpoint);
+            self.untouched = untouched;
+            #[cfg(feature = "alloc-budget")]
+            {
+                self.budget = budget;
+            }
+            #[cfg(feature = "stats")]
+            {
+                let pages_requested = self.stats.pages_requested;
+                self.stats = stats;
+                self.stats.pages_requested = pages_requested;
+                if self.start != checkpoint.start {
+                    // The heap has moved on to the granted pages, leaving the rest of the old
+                    // one unused
+                    self.stats.bytes_fragmented += upper_limit - checkpoint.next;
+                }
+            }
+            #[cfg(feature = "debug-log")]
+            {
+                self.log = log;
+            }
+        }
+        starts
+    }
+
+    /// Returns whether all of the given `Layout`s can be allocated one after the other.
+    ///
+    /// The allocations are planned on a copy of the heap whose page source grants every grow, so
+    /// nothing is requested and nobody is notified. A trap the plan runs into is raised for this
+    /// heap though, e.g. when it would have to grow while growing is forbidden.
+    #[cfg(feature = "std")]
+    fn fits_all(&mut self, layouts: &[Layout]) -> bool {
+        let mut plan = InnerAlloc {
+            start: self.start,
+            next: self.next,
+            upper_limit: self.upper_limit,
+            untouched: self.untouched,
+            requested_pages: self.requested_pages,
+            frozen: self.frozen,
+            pages: PlannedPages(self.pages.memory_size()),
+            config: self.config,
+            #[cfg(feature = "free-list")]
+            free: self.free,
+            #[cfg(feature = "size-classes")]
+            classes: self.classes,
+            #[cfg(feature = "forbid-grow")]
+            forbid_grow: self.forbid_grow,
+            defer_traps: true,
+            trap: None,
+            #[cfg(feature = "alloc-budget")]
+            budget: self.budget,
+            #[cfg(feature = "observer")]
+            observer: None,
+            #[cfg(feature = "debug-log")]
+            log: EventLog::new(),
+            #[cfg(feature = "debug-tracking")]
+            live: LiveAllocations::new(),
+            #[cfg(feature = "stats")]
+            stats: AllocStats::new(),
+            #[cfg(feature = "export-metrics")]
+            exported: false,
+        };
+
+        // Planning must not touch the memory, so we only bump the heap even if every allocation
+        // is zeroed otherwise
+        let fits = layouts.iter().all(|&layout| plan.bump(layout).is_some());
+        if let Some(trap) = plan.trap {
+            self.trap(trap);
+        }
+        fits
+    }
+
+    /// Returns the current state of the heap, to which it can be rewound later on.
+    fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            start: self.start,
+            next: self.next,
+            #[cfg(feature = "free-list")]
+            free: self.free,
+            #[cfg(feature = "size-classes")]
+            classes: self.classes,
+        }
+    }
+
+    /// Releases everything which has been allocated since the `checkpoint` has been taken.
+    ///
+    /// Memory which has been freed in between is forgotten, and memory which was free at the
+    /// checkpoint is free again, even if it has been handed out again in between.
+    fn rewind(&mut self, checkpoint: Checkpoint) {
+        self.next = if self.start == checkpoint.start {
+            checkpoint.next
+        } else {
+            // The heap has moved on to new pages since, all of which have been allocated after
+            // the checkpoint.
+            self.start
+        };
+
+        #[cfg(feature = "free-list")]
+        {
+            self.free = checkpoint.free;
+        }
+        #[cfg(feature = "size-classes")]
+        {
+            self.classes = checkpoint.classes;
+        }
+        #[cfg(feature = "debug-tracking")]
+        self.live.forget_from(checkpoint.next);
+    }
+
+    /// Grows the heap by exactly `pages` pages, see [`Bump::try_grow_pages`].
+    fn try_grow_pages(&mut self, pages: usize) -> Option<usize> {
+        if pages == 0 {
+            return Some(self.upper_limit)
+        }
+
+        // Make sure that we can use the pages before we request them
+        let expected_start = self.page_addr(self.pages.memory_size())?;
+        self.page_end(expected_start, pages)?;
+
+        let page_start = self.request_pages(pages)?;
+        let upper_limit = self.page_end(page_start, pages)?;
+        #[cfg(feature = "stats")]
+        {
+            self.stats.pages_requested += pages;
+        }
+
+        if page_start != self.upper_limit {
+            #[cfg(feature = "stats")]
+            {
+                self.stats.bytes_fragmented += self.upper_limit - self.next;
+            }
+            self.start = page_start;
+            self.next = page_start;
+        }
+        self.upper_limit = upper_limit;
+        self.check_limit();
+        #[cfg(feature = "export-metrics")]
+        self.export_metrics();
+
+        Some(upper_limit)
+    }
+
+    /// Claims all pages between the end of the heap and the end of the memory, see
+    /// [`Bump::resync`].
+    fn resync(&mut self) {
+        // Without any pages we do not know which part of the memory is free for us to use.
+        if self.upper_limit == 0 {
+            return
+        }
+
+        let memory_end = match self.page_addr(self.pages.memory_size()) {
+            Some(memory_end) if memory_end > self.upper_limit => memory_end,
+            _ => return,
+        };
+        let mut pages = (memory_end - self.upper_limit) / PAGE_SIZE;
+        if let Some(max_pages) = self.config.max_pages {
+            pages = core::cmp::min(pages, max_pages.saturating_sub(self.requested_pages));
+        }
+        if pages == 0 {
+            return
+        }
+
+        // The claimed pages count as requested by us, just like the ones we grow ourselves
+        self.requested_pages += pages;
+        #[cfg(feature = "stats")]
+        {
+            self.stats.pages_requested += pages;
+        }
+        self.upper_limit += pages * PAGE_SIZE;
+        self.check_limit();
+        #[cfg(feature = "export-metrics")]
+        self.export_metrics();
+    }
+
+    /// Tries to allocate enough memory on the heap for the given `Layout`.
+    ///
+    /// With the `always-zero` crate feature enabled every allocation is zeroed, see
+    /// [`InnerAlloc::alloc_zeroed`].
+    #[inline]
+    fn alloc(&mut self, layout: Layout) -> Option<usize> {
+        if cfg!(feature = "always-zero") {
+            self.alloc_zeroed(layout)
+        } else {
+            self.bump(layout)
+        }
+    }
+
+    /// Tries to allocate enough zeroed memory on the heap for the given `Layout`.
+    ///
+    /// A new page in Wasm is guaranteed to already be zero initialized, so we only have to zero
+    /// the bytes of the allocation which have been handed out before.
+    ///
+    /// See: https://webassembly.github.io/spec/core/exec/modules.html#growing-memories
+    fn alloc_zeroed(&mut self, layout: Layout) -> Option<usize> {
+        let untouched = self.untouched;
+        let alloc_start = self.bump(layout)?;
+
+        if alloc_start < untouched {
+            let dirty_end = core::cmp::min(untouched, alloc_start + layout.size());
+            // SAFETY: We just handed out the bytes up to `dirty_end` for this allocation.
+            unsafe {
+                core::ptr::write_bytes(
+                    alloc_start as *mut u8,
+                    0,
+                    dirty_end - alloc_start,
+                );
+            }
+        }
+
+        Some(alloc_start)
+    }
+
+    /// Tries to allocate enough memory on the heap for the given `Layout` with every byte set to
+    /// `byte`.
+    fn alloc_filled(&mut self, layout: Layout, byte: u8) -> Option<*mut u8> {
+        if byte == 0 {
+            return self.alloc_zeroed(layout).map(|start| start as *mut u8)
+        }
+
+        // Every byte is overwritten anyway, so there is no point in zeroing them first
+        let ptr = self.bump(layout)? as *mut u8;
+        // SAFETY: We just handed out `layout.size()` bytes starting at `ptr`.
+        unsafe { core::ptr::write_bytes(ptr, byte, layout.size()) };
+        Some(ptr)
+    }
+
+    /// Tries to bump the heap for the given `Layout`, recording the outcome in the statistics.
+    #[inline]
+    fn bump(&mut self, layout: Layout) -> Option<usize> {
+        let alloc_start = if self.frozen == 0 {
+            let alloc_start = self.try_bump(layout);
+            #[cfg(feature = "observer")]
+            if let (Some(observer), None) = (self.observer, alloc_start) {
+                observer.on_oom(layout);
+            }
+            #[cfg(feature = "debug-log")]
+            self.log.record(AllocEvent::Alloc {
+                layout,
+                start: alloc_start,
+            });
+            alloc_start
+        } else {
+            if self.config.trap_when_frozen {
+                self.trap(Trap::Frozen);
+            }
+            None
+        };
+
+        #[cfg(feature = "alloc-budget")]
+        if alloc_start.is_some() {
+            self.charge_budget(self.allocation_size(layout).unwrap_or(0));
+        }
+
+        // Empty allocations do not occupy any memory of the heap, so there is nothing to track
+        #[cfg(feature = "debug-tracking")]
+        if let (Some(start), None) = (alloc_start, self.dangling(layout)) {
+            self.live.insert(start, layout.size());
+        }
+
+        #[cfg(feature = "stats")]
+        match alloc_start {
+            Some(_) => {
+                self.stats.allocations += 1;
+                self.stats.bytes_allocated += self.allocation_size(layout).unwrap_or(0);
+            }
+            None => self.stats.failed_allocations += 1,
+        }
+
+        #[cfg(feature = "observer")]
+        if let (Some(observer), Some(start)) = (self.observer, alloc_start) {
+            observer.on_alloc(start, layout);
+        }
+
+        self.check_limit();
+        #[cfg(feature = "export-metrics")]
+        self.export_metrics();
+        alloc_start
+    }
+
+    /// Publishes the statistics if this heap exports them, see [`Bump::exporting_metrics`].
+    #[cfg(feature = "export-metrics")]
+    #[inline]
+    fn export_metrics(&self) {
+        if self.exported {
+            metrics::publish(&self.stats);
+        }
+    }
+
+    /// Asserts in debug builds that the heap does not extend beyond the memory reserved for it.
+    ///
+    /// Otherwise the next allocation which fits would hand out memory we never requested.
+    #[inline]
+    fn check_limit(&self) {
+        debug_assert!(
+            self.next <= self.upper_limit,
+            "the heap continues at {:#x} beyond its end at {:#x}",
+            self.next,
+            self.upper_limit
+        );
+    }
+
+    /// Tries to bump the heap for the given `Layout`. If there is not enough room on the heap
+    /// it'll try and grow it by a page.
+    ///
+    /// Note: This implementation results in internal fragmentation when allocating across pages.
+    ///
+    /// All fallible computations happen before the state of the heap is touched, so a failed
+    /// allocation never leaves behind a partially updated heap.
+    fn try_bump(&mut self, layout: Layout) -> Option<usize> {
+        let aligned_size = self.allocation_size(layout)?;
+
+        let align = self.alignment(layout);
+
+        if let Some(alloc_start) = self.dangling(layout) {
+            return Some(alloc_start)
+        }
+
+        #[cfg(feature = "alloc-budget")]
+        if !self.within_budget(aligned_size) {
+            return None
+        }
+
+        #[cfg(feature = "size-classes")]
+        if let Some(alloc_start) = self.classes.take(aligned_size, align) {
+            return Some(alloc_start)
+        }
+
+        #[cfg(feature = "free-list")]
+        if let Some(alloc_start) = self.free.take(aligned_size, align) {
+            return Some(alloc_start)
+        }
+
+        let alloc_start = align_up(non_null(self.next), align)?;
+        let alloc_end = self.offset_addr(alloc_start, aligned_size)?;
+
+        if alloc_end > self.upper_limit {
+            // The page source hands out the pages right after its current memory
+            let expected_start = self.page_addr(self.pages.memory_size())?;
+            let grow_pages = self.grow_pages_for(expected_start, aligned_size, align)?;
+            let page_start = self.request_pages(grow_pages)?;
+            let upper_limit = self.page_end(page_start, grow_pages)?;
+            #[cfg(feature = "stats")]
+            {
+                self.stats.pages_requested += grow_pages;
+                self.stats.bytes_fragmented += self.upper_limit - self.next;
+            }
+
+            // The pages are ours now, so we continue from them even if the allocation does not
+            // fit into them after all.
+            if page_start != self.upper_limit {
+                // The new pages are not adjacent to our heap, e.g. since they are our first ones,
+                // so we have to give up on the old heap and start over from the new pages.
+                self.start = page_start;
+            }
+            self.upper_limit = upper_limit;
+            self.next = page_start;
+
+            let alloc_start = align_up(non_null(page_start), align)?;
+            let next = self
+                .offset_addr(alloc_start, aligned_size)
+                // Only a page source which contradicts its own memory size hands out the null
+                // address unexpectedly
+                .filter(|&next| next <= upper_limit)?;
+            #[cfg(feature = "stats")]
+            {
+                self.stats.alignment_waste += alloc_start - page_start;
+            }
+            self.next = next;
+            self.untouched = core::cmp::max(self.untouched, self.next);
+
+            Some(alloc_start)
+        } else {
+            #[cfg(feature = "stats")]
+            {
+                self.stats.alignment_waste += alloc_start - self.next;
+            }
+            self.next = alloc_end;
+            self.untouched = core::cmp::max(self.untouched, self.next);
+            Some(alloc_start)
+        }
+    }
+
+    /// Returns the number of bytes the heap is bumped by for an allocation of the given `Layout`.
+    ///
+    /// This is the size of the `Layout` padded to its alignment and rounded up to the configured
+    /// granularity and minimum alignment.
+    #[inline]
+    fn allocation_size(&self, layout: Layout) -> Option<usize> {
+        let multiple = core::cmp::max(self.config.granularity, self.config.min_align);
+        align_up(layout.pad_to_align().size(), multiple)
+    }
+
+    /// Returns the dangling address handed out for the given `Layout` if it is empty.
+    ///
+    /// None of the bytes of an empty allocation are ever accessed, so it does not need any
+    /// memory of the heap, let alone a new page or padding for its alignment. Just like `std` we
+    /// hand out its alignment as its address instead, which is aligned and never null.
+    #[inline]
+    fn dangling(&self, layout: Layout) -> Option<usize> {
+        (layout.size() == 0).then(|| layout.align())
+    }
+
+    /// Returns the alignment of an allocation of the given `Layout`, taking the configured
+    /// minimum alignment into account.
+    #[inline]
+    fn alignment(&self, layout: Layout) -> usize {
+        core::cmp::max(layout.align(), self.config.min_align)
+    }
+
+    /// Moves `value` into newly allocated memory on the heap.
+    fn alloc_value<T>(&mut self, value: T) -> Option<*mut T> {
+        let ptr = self.alloc(Layout::new::<T>())? as *mut T;
+        // SAFETY: We just allocated enough memory for a `T` at a suitable alignment.
+        unsafe { ptr.write(value) };
+        Some(ptr)
+    }
+
+    /// Resizes the allocation starting at `start` to `new_size` bytes.
+    ///
+    /// # Safety
+    ///
+    /// `start` must be the start of a live allocation with the given `layout`.
+    unsafe fn realloc(
+        &mut self,
+        start: usize,
+        layout: Layout,
+        new_size: usize,
+    ) -> Option<usize> {
+        let new_layout = Layout::from_size_align(new_size, layout.align()).ok()?;
+        let old_size = self.allocation_size(layout)?;
+        let new_alloc_size = self.allocation_size(new_layout)?;
+
+        if new_alloc_size <= old_size {
+            #[cfg(feature = "free-list")]
+            self.free.push(start + new_alloc_size, start + old_size);
+            self.resized_in_place(start, layout, new_size);
+            return Some(start)
+        }
+
+        if self.frozen == 0 && self.extend_tail(start, old_size, new_alloc_size).is_some()
+        {
+            self.resized_in_place(start, layout, new_size);
+            return Some(start)
+        }
+
+        let new_start = self.alloc(new_layout)?;
+        core::ptr::copy_nonoverlapping(
+            start as *const u8,
+            new_start as *mut u8,
+            layout.size(),
+        );
+        #[cfg(feature = "stats")]
+        {
+            self.stats.bytes_copied += layout.size();
+        }
+        self.dealloc(start, layout);
+
+        Some(new_start)
+    }
+
+    /// Records that the allocation of the given `Layout` at `start` has been resized to
+    /// `new_size` bytes in place.
+    #[inline]
+    fn resized_in_place(&mut self, _start: usize, _layout: Layout, _new_size: usize) {
+        #[cfg(feature = "debug-log")]
+        self.log.record(AllocEvent::Resize {
+            start: _start,
+            layout: _layout,
+            new_size: _new_size,
+        });
+        #[cfg(feature = "debug-tracking")]
+        self.live.resize(_start, _new_size);
+        self.check_limit();
+        #[cfg(feature = "export-metrics")]
+        self.export_metrics();
+    }
+
+    /// Grows the allocation of `old_size` bytes starting at `start` to `new_size` bytes in place
+    /// if it is the last allocation on the heap.
+    ///
+    /// The memory is contiguous, so the last allocation can keep growing into new pages as long
+    /// as they are adjacent to the heap. Returns `None` if the allocation has to move instead.
+    fn extend_tail(
+        &mut self,
+        start: usize,
+        old_size: usize,
+        new_size: usize,
+    ) -> Option<()> {
+        if self.offset_addr(start, old_size)? != self.next {
+            return None
+        }
+        let new_end = self.offset_addr(start, new_size)?;
+
+        #[cfg(feature = "alloc-budget")]
+        if !self.within_budget(new_size - old_size) {
+            return None
+        }
+
+        if new_end > self.upper_limit {
+            // Pages requested by someone else in the meantime lie in between
+            if self.page_addr(self.pages.memory_size())? != self.upper_limit {
+                return None
+            }
+            let grow_pages = self.grow_pages(required_pages(new_end - self.upper_limit)?);
+            let page_start = self.request_pages(grow_pages)?;
+            let upper_limit = self.page_end(page_start, grow_pages)?;
+            #[cfg(feature = "stats")]
+            {
+                self.stats.pages_requested += grow_pages;
+            }
+
+            if page_start != self.upper_limit {
+                // The page source did not grow the memory where it said it would. We still got
+                // the pages, so the heap moves on to them like for any other allocation.
+                #[cfg(feature = "stats")]
+                {
+                    self.stats.bytes_fragmented += self.upper_limit - self.next;
+                }
+                self.start = page_start;
+                self.next = page_start;
+                self.upper_limit = upper_limit;
+                return None
+            }
+            self.upper_limit = upper_limit;
+        }
+
+        if cfg!(feature = "always-zero") && self.next < self.untouched {
+            let dirty_end = core::cmp::min(self.untouched, new_end);
+            // SAFETY: The bytes up to `new_end` now belong to the allocation.
+            unsafe {
+                core::ptr::write_bytes(self.next as *mut u8, 0, dirty_end - self.next);
+            }
+        }
+
+        #[cfg(feature = "alloc-budget")]
+        self.charge_budget(new_size - old_size);
+        #[cfg(feature = "stats")]
+        {
+            self.stats.bytes_allocated += new_size - old_size;
+        }
+        self.next = new_end;
+        self.untouched = core::cmp::max(self.untouched, self.next);
+        Some(())
+    }
+
+    /// Releases the allocation starting at `start`, see [`Bump::dealloc`].
+    ///
+    /// With the `free-list` crate feature enabled the allocation is put onto the free list, so
+    /// that its memory can be handed out again.
+    #[inline]
+    fn dealloc(&mut self, _start: usize, _layout: Layout) {
+        #[cfg(feature = "debug-log")]
+        self.log.record(AllocEvent::Dealloc {
+            start: _start,
+            layout: _layout,
+        });
+
+        #[cfg(feature = "observer")]
+        if let Some(observer) = self.observer {
+            observer.on_dealloc(_start, _layout);
+        }
+
+        #[cfg(feature = "debug-tracking")]
+        self.live.remove(_start);
+
+        #[cfg(feature = "stats")]
+        {
+            self.stats.deallocations += 1;
+            self.stats.bytes_freed += self.allocation_size(_layout).unwrap_or(0);
+        }
+
+        // Blocks of a size class go into their bin, everything else onto the free list
+        #[cfg(feature = "size-classes")]
+        let _binned = self
+            .allocation_size(_layout)
+            .is_some_and(|size| self.classes.push(_start, size));
+        #[cfg(not(feature = "size-classes"))]
+        let _binned = false;
+
+        #[cfg(feature = "free-list")]
+        if let (false, Some(end)) = (
+            _binned,
+            self.allocation_size(_layout)
+                .and_then(|size| _start.checked_add(size)),
+        ) {
+            self.free.push(_start, end);
+        }
+
+        self.check_limit();
+    }
+
+    /// Tries to allocate enough memory on the heap for the given `Layout` and attributes the
+    /// allocated bytes to `tag`.
+    #[cfg(feature = "debug-tags")]
+    fn alloc_tagged(&mut self, layout: Layout, tag: &'static str) -> Option<usize> {
+        let alloc_start = self.alloc(layout)?;
+        self.stats.tags.record(tag, self.allocation_size(layout)?);
+        #[cfg(feature = "debug-tracking")]
+        self.live.tag(alloc_start, tag);
+        Some(alloc_start)
+    }
+
+    /// Tries to allocate enough memory on the heap for an array of `n` values of type `T`.
+    ///
+    /// Returns the start of the allocation together with the `Layout` of the array, see
+    /// [`Bump::alloc_array`].
+    #[cfg(feature = "std")]
+    fn alloc_array<T>(&mut self, n: usize) -> Result<(usize, Layout), AllocError> {
+        let size = core::mem::size_of::<T>()
+            .checked_mul(n)
+            .ok_or(AllocError::SizeOverflow)?;
+        let layout = layout(size, core::mem::align_of::<T>())?;
+        let start = self.alloc(layout).ok_or(AllocError::OutOfMemory)?;
+        Ok((start, layout))
+    }
+}
+
+/// Rounds `addr` up to the next multiple of `align`, which must be a power of two.
+///
+/// Returns `None` if the rounded address cannot be represented.
+#[inline]
+pub(crate) fn align_up(addr: usize, align: usize) -> Option<usize> {
+    addr.checked_add(align - 1).map(|addr| addr & !(align - 1))
+}
+
+/// The number of bytes at the very beginning of the memory which are never handed out.
+///
+/// An allocation starting at the null address would be mistaken for a failed one by the callers
+/// of [`GlobalAlloc::alloc`], so we reserve the first word of the memory.
+pub(crate) const NULL_GUARD: usize = core::mem::size_of::<usize>();
+
+/// Moves `addr` past the reserved first word of the memory, see [`NULL_GUARD`].
+///
+/// This only matters if the heap starts at the very beginning of the memory.
+#[inline]
+fn non_null(addr: usize) -> usize {
+    core::cmp::max(addr, NULL_GUARD)
+}
+
+/// Calculates the number of pages of memory needed for an allocation of `size` bytes.
+///
+/// This function rounds up to the next page. For example, if we have an allocation of
+/// `size = PAGE_SIZE / 2` this function will indicate that one page is required to satisfy
+/// the allocation.
+#[inline]
+fn required_pages(size: usize) -> Option<usize> {
+    size.checked_add(PAGE_SIZE - 1)
+        .and_then(|num| num.checked_div(PAGE_SIZE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(feature = "debug-log")]
+    use crate::event_log::{
+        replay,
+        ReplayResult,
+    };
+    #[cfg(feature = "free-list")]
+    use crate::free_list::Span;
+    #[cfg(feature = "debug-tags")]
+    use crate::stats::MAX_TAGS;
+    use crate::{
+        emulated::EmulatedHeap,
+        failing::FailingPages,
+    };
+    use std::mem::{
+        align_of,
+        size_of,
+    };
+
+    /// Hands out exactly the bytes requested, so that tests can reason about the heap byte by
+    /// byte.
+    const BYTE_EXACT: Config = Config::DEFAULT.min_align(1);
+
+    /// The test pages start at the null address, so the first word of the heap is never handed
+    /// out.
+    const FIRST: usize = NULL_GUARD;
+
+    /// The address widths the allocator is tested with: the one of the host and the 32-bit one
+    /// of Wasm.
+    ///
+    /// The test pages start at low addresses, so the results must not depend on the width.
+    const ADDRESS_WIDTHS: [Config; 2] = [Config::DEFAULT, Config::DEFAULT.addr32()];
+
+    #[test]
+    fn can_alloc_no_bytes() {
+        for config in ADDRESS_WIDTHS {
+            let mut inner = InnerAlloc::with_config(Pages::new(), config);
+
+            // An empty allocation does not need any memory, so it starts at a dangling address
+            let layout = Layout::new::<()>();
+            assert_eq!(inner.alloc(layout), Some(layout.align()));
+            assert_eq!(inner.upper_limit, 0);
+            assert_eq!(inner.next, 0);
+        }
+    }
+
+    #[test]
+    fn can_alloc_a_byte() {
+        for config in ADDRESS_WIDTHS {
+            let mut inner = InnerAlloc::with_config(Pages::new(), config);
+
+            let layout = Layout::new::<u8>();
+            assert_eq!(inner.alloc(layout), Some(FIRST));
+
+            let expected_limit =
+                PAGE_SIZE * required_pages(layout.pad_to_align().size()).unwrap();
+            assert_eq!(inner.upper_limit, expected_limit);
+
+            // Even a single byte takes up a whole word, so that the next allocation is word aligned
+            let expected_alloc_start = FIRST + size_of::<usize>();
+            assert_eq!(inner.next, expected_alloc_start);
+        }
+    }
+
+    #[test]
+    fn heap_begins_at_the_base_address() {
+        const BASE: usize = 4 * PAGE_SIZE;
+        let mut inner = InnerAlloc::with_base(BASE);
+
+        // Away from the null address there is no reserved word to skip
+        assert_eq!(inner.alloc(Layout::new::<u64>()), Some(BASE));
+        assert_eq!(inner.start, BASE);
+        assert_eq!(inner.next, BASE + size_of::<u64>());
+        assert_eq!(inner.upper_limit, BASE + PAGE_SIZE);
+
+        // The rest of the page is too small, so the heap grows by an adjacent page
+        let layout = Layout::new::<[u8; PAGE_SIZE]>();
+        assert_eq!(inner.alloc(layout), Some(BASE + PAGE_SIZE));
+        assert_eq!(inner.start, BASE);
+        assert_eq!(inner.next, BASE + 2 * PAGE_SIZE);
+        assert_eq!(inner.upper_limit, BASE + 2 * PAGE_SIZE);
+        assert_eq!(inner.requested_pages, 2);
+        assert_eq!(inner.pages.memory_size(), BASE / PAGE_SIZE + 2);
+    }
+
+    #[test]
+    fn can_alloc_a_foobarbaz() {
+        struct FooBarBaz {
+            _foo: u32,
+            _bar: u128,
+            _baz: (u16, bool),
+        }
+
+        for config in ADDRESS_WIDTHS {
+            let mut inner = InnerAlloc::with_config(Pages::new(), config);
+            let layout = Layout::new::<FooBarBaz>();
+            let mut total_size = 0;
+
+            let allocations = 3;
+            for _ in 0..allocations {
+                assert!(inner.alloc(layout).is_some());
+                total_size += layout.pad_to_align().size();
+            }
+
+            let expected_limit = PAGE_SIZE * required_pages(total_size).unwrap();
+            assert_eq!(inner.upper_limit, expected_limit);
+
+            // The first allocation skips up to the next `u128` boundary after the reserved word
+            let expected_alloc_start = align_up(FIRST, align_of::<FooBarBaz>()).unwrap()
+                + allocations * size_of::<FooBarBaz>();
+            assert_eq!(inner.next, expected_alloc_start);
+        }
+    }
+
+    #[test]
+    fn can_alloc_across_pages() {
+        struct Foo {
+            _foo: [u8; PAGE_SIZE - FIRST - 1],
+        }
+
+        for config in ADDRESS_WIDTHS {
+            let mut inner = InnerAlloc::with_config(Pages::new(), config.min_align(1));
+            // First, let's allocate a struct which _almost_ fills the rest of the page
+            let layout = Layout::new::<Foo>();
+            assert_eq!(inner.alloc(layout), Some(FIRST));
+
+            let expected_limit =
+                PAGE_SIZE * required_pages(layout.pad_to_align().size()).unwrap();
+            assert_eq!(inner.upper_limit, expected_limit);
+
+            let expected_alloc_start = FIRST + size_of::<Foo>();
+            assert_eq!(inner.next, expected_alloc_start);
+
+            // Now we'll allocate two bytes which will push us over to the next page
+            let layout = Layout::new::<u16>();
+            assert_eq!(inner.alloc(layout), Some(PAGE_SIZE));
+
+            let expected_limit = 2 * PAGE_SIZE;
+            assert_eq!(inner.upper_limit, expected_limit);
+
+            // Notice that we start the allocation on the second page, instead of making use of the
+            // remaining byte on the first page
+            let expected_alloc_start = PAGE_SIZE + size_of::<u16>();
+            assert_eq!(inner.next, expected_alloc_start);
+        }
+    }
+
+    #[test]
+    fn can_alloc_multiple_pages() {
+        struct Foo {
+            _foo: [u8; 2 * PAGE_SIZE - FIRST],
+        }
+
+        for config in ADDRESS_WIDTHS {
+            let mut inner = InnerAlloc::with_config(Pages::new(), config);
+            let layout = Layout::new::<Foo>();
+            assert_eq!(inner.alloc(layout), Some(FIRST));
+
+            let expected_limit =
+                PAGE_SIZE * required_pages(layout.pad_to_align().size()).unwrap();
+            assert_eq!(inner.upper_limit, expected_limit);
+
+            let expected_alloc_start = FIRST + size_of::<Foo>();
+            assert_eq!(inner.next, expected_alloc_start);
+
+            // Now we want to make sure that the state of our allocator is correct for any subsequent
+            // allocations
+            let layout = Layout::new::<u8>();
+            assert_eq!(inner.alloc(layout), Some(2 * PAGE_SIZE));
+
+            let expected_limit = 3 * PAGE_SIZE;
+            assert_eq!(inner.upper_limit, expected_limit);
+
+            let expected_alloc_start = 2 * PAGE_SIZE + size_of::<usize>();
+            assert_eq!(inner.next, expected_alloc_start);
+        }
+    }
+
+    #[test]
+    fn can_alloc_many_pages_at_once() {
+        for &pages in &[1, 2, 10, 100] {
+            let mut inner = InnerAlloc::new();
+
+            // Leave room for a single word at the end of the last page
+            let size = pages * PAGE_SIZE - FIRST - size_of::<usize>();
+            let layout = Layout::from_size_align(size, 1).unwrap();
+            assert_eq!(inner.alloc(layout), Some(FIRST), "{} pages", pages);
+
+            let expected_limit = PAGE_SIZE * required_pages(FIRST + size).unwrap();
+            assert_eq!(expected_limit, pages * PAGE_SIZE);
+            assert_eq!(inner.upper_limit, expected_limit, "{} pages", pages);
+
+            // A tiny allocation still fits into the last word...
+            let tiny = Layout::new::<u8>();
+            assert_eq!(inner.alloc(tiny), Some(FIRST + size), "{} pages", pages);
+            assert_eq!(inner.upper_limit, expected_limit, "{} pages", pages);
+
+            // ...but the next one starts a new page
+            assert_eq!(inner.alloc(tiny), Some(expected_limit), "{} pages", pages);
+            assert_eq!(
+                inner.upper_limit,
+                expected_limit + PAGE_SIZE,
+                "{} pages",
+                pages
+            );
+        }
+    }
+
+    /// Allocations of just over half a page never fit next to each other, so every one of them
+    /// starts a new page and leaves the tail of the previous page unused.
+    ///
+    /// This is the steady state of the internal fragmentation across pages: just under half of
+    /// the reserved memory is lost.
+    #[test]
+    fn fragmentation_of_allocations_larger_than_half_a_page() {
+        const ALLOCATIONS: usize = 100;
+        let mut inner = InnerAlloc::new();
+
+        let layout = Layout::new::<[u8; PAGE_SIZE / 2 + 1]>();
+        let size = inner.allocation_size(layout).unwrap();
+
+        let mut fragmented = 0;
+        for i in 0..ALLOCATIONS {
+            let tail = inner.upper_limit - inner.next;
+            assert_eq!(inner.alloc(layout), Some(non_null(i * PAGE_SIZE)));
+            fragmented += tail;
+        }
+
+        // The tail of every page but the last one is lost, the first page lost the reserved word
+        // at its start as well
+        assert_eq!(fragmented, (ALLOCATIONS - 1) * (PAGE_SIZE - size) - FIRST);
+        assert_eq!(inner.upper_limit, ALLOCATIONS * PAGE_SIZE);
+        assert!(2 * fragmented < inner.upper_limit);
+        #[cfg(feature = "stats")]
+        assert_eq!(inner.stats.bytes_fragmented, fragmented);
+    }
+
+    /// A single word at the start of a page followed by a whole page loses all but a word of
+    /// the page, which is the most a single grow can ever lose.
+    ///
+    /// Repeating this pattern loses almost half of the reserved memory as well, just like
+    /// allocations larger than half a page do.
+    #[test]
+    fn fragmentation_worst_case_per_grow() {
+        const ROUNDS: usize = 50;
+        let word = size_of::<usize>();
+        let mut inner = InnerAlloc::new();
+
+        for i in 0..ROUNDS {
+            assert_eq!(
+                inner.alloc(Layout::new::<usize>()),
+                Some(non_null(2 * i * PAGE_SIZE))
+            );
+            assert_eq!(
+                inner.alloc(Layout::new::<[u8; PAGE_SIZE]>()),
+                Some((2 * i + 1) * PAGE_SIZE)
+            );
+        }
+
+        let used = ROUNDS * (word + PAGE_SIZE);
+        assert_eq!(inner.upper_limit - used, ROUNDS * (PAGE_SIZE - word));
+    }
+
+    #[test]
+    fn fragmentation_of_a_nearly_full_page() {
+        let word = size_of::<usize>();
+        let mut inner = InnerAlloc::from_parts(PAGE_SIZE - word, PAGE_SIZE);
+
+        // Only a word is left on the page, so two words move on to the next page and lose it
+        assert_eq!(inner.alloc(Layout::new::<[usize; 2]>()), Some(PAGE_SIZE));
+        assert_eq!(inner.next, PAGE_SIZE + 2 * word);
+        assert_eq!(inner.upper_limit, 2 * PAGE_SIZE);
+    }
+
+    #[test]
+    fn aligns_allocations_after_odd_sized_ones() {
+        for config in ADDRESS_WIDTHS {
+            let mut inner = InnerAlloc::with_config(Pages::new(), config.min_align(1));
+
+            assert_eq!(inner.alloc(Layout::new::<u8>()), Some(FIRST));
+
+            // We skip the remaining bytes up to the next `u64` boundary
+            let layout = Layout::new::<u64>();
+            assert_eq!(inner.alloc(layout), Some(FIRST + layout.align()));
+
+            let expected_alloc_start = FIRST + layout.align() + size_of::<u64>();
+            assert_eq!(inner.next, expected_alloc_start);
+        }
+    }
+
+    #[test]
+    fn can_alloc_beyond_page_alignment() {
+        for config in ADDRESS_WIDTHS {
+            let mut inner = InnerAlloc::with_config(Pages::new(), config);
+
+            assert_eq!(inner.alloc(Layout::new::<u8>()), Some(FIRST));
+
+            // The new pages start at `PAGE_SIZE`, so we need an extra page to reach the next
+            // boundary of the requested alignment
+            let layout = Layout::from_size_align(1, 2 * PAGE_SIZE).unwrap();
+            assert_eq!(inner.alloc(layout), Some(2 * PAGE_SIZE));
+            assert_eq!(inner.upper_limit, 4 * PAGE_SIZE);
+        }
+    }
+
+    #[test]
+    fn can_alloc_empty_array() {
+        let mut inner = InnerAlloc::new();
+
+        let (start, layout) = inner.alloc_array::<u64>(0).unwrap();
+        assert_eq!(start, align_of::<u64>());
+        assert_eq!(layout.size(), 0);
+        assert_eq!(inner.next, 0);
+    }
+
+    #[test]
+    fn can_alloc_array() {
+        let mut inner = InnerAlloc::new();
+
+        let (start, layout) = inner.alloc_array::<u32>(10).unwrap();
+        assert_eq!(start, FIRST);
+        assert_eq!(layout, Layout::new::<[u32; 10]>());
+        assert_eq!(inner.next, FIRST + 10 * size_of::<u32>());
+    }
+
+    #[test]
+    fn cannot_alloc_array_if_size_overflows() {
+        let mut bump = Bump::new(Pages::new());
+
+        assert_eq!(
+            bump.alloc_array::<u64>(usize::MAX),
+            Err(AllocError::SizeOverflow)
+        );
+        assert_eq!(
+            bump.alloc_array::<u64>(usize::MAX / size_of::<u64>() + 1),
+            Err(AllocError::SizeOverflow)
+        );
+        // The size does not overflow, but exceeds the address space once rounded up
+        assert_eq!(
+            bump.alloc_array::<u64>(usize::MAX / size_of::<u64>()),
+            Err(AllocError::SizeOverflow)
+        );
+
+        // Nothing has been allocated by the failed attempts
+        assert_eq!(bump.inner.next, 0);
+        assert_eq!(bump.inner.upper_limit, 0);
+    }
+
+    #[test]
+    fn cannot_alloc_array_beyond_the_memory() {
+        let config = Config::DEFAULT.max_pages(1);
+        let mut bump = Bump::with_config(Pages::new(), config);
+
+        assert_eq!(
+            bump.alloc_array::<u8>(2 * PAGE_SIZE),
+            Err(AllocError::OutOfMemory)
+        );
+    }
+
+    #[test]
+    fn can_alloc_array_of_over_aligned_type() {
+        let mut inner = InnerAlloc::new();
+
+        #[repr(align(64))]
+        struct Aligned {
+            _foo: u8,
+        }
+
+        assert!(inner.alloc(Layout::new::<u8>()).is_some());
+
+        let (start, layout) = inner.alloc_array::<Aligned>(3).unwrap();
+        assert_eq!(layout.align(), 64);
+        assert_eq!(start % 64, 0);
+        assert_eq!(start, 64);
+        assert_eq!(inner.next, start + 3 * 64);
+    }
+
+    /// Allocates a `[u8; 16]` from the emulated heap, dirties it and rewinds the heap so that the
+    /// next allocation reuses the same bytes.
+    fn dirty_and_rewind(inner: &mut InnerAlloc<EmulatedHeap>) -> Layout {
+        let layout = Layout::new::<[u8; 16]>();
+        let start = inner.bump(layout).unwrap();
+        unsafe { core::ptr::write_bytes(start as *mut u8, 0xFF, layout.size()) };
+
+        inner.next = start;
+        layout
+    }
+
+    #[test]
+    fn alloc_zeroed_zeroes_reused_memory() {
+        let mut inner = InnerAlloc::with_page_source(EmulatedHeap::new(1));
+        let layout = dirty_and_rewind(&mut inner);
+
+        let start = inner.alloc_zeroed(layout).unwrap();
+        let bytes =
+            unsafe { core::slice::from_raw_parts(start as *const u8, layout.size()) };
+        assert!(bytes.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn alloc_zeroed_zeroes_reused_memory_across_pages() {
+        // Before its first grow the emulated memory could start at the null address as far as the
+        // allocator knows, so it plans for an extra page to skip the reserved first word
+        let mut inner = InnerAlloc::with_page_source(EmulatedHeap::new(3));
+        let heap = Layout::new::<[u8; 2 * PAGE_SIZE]>();
+        let start = inner.bump(heap).unwrap();
+        unsafe { core::ptr::write_bytes(start as *mut u8, 0xFF, heap.size()) };
+
+        // Reuse the second half of the first page and the first half of the second one
+        inner.next = start + PAGE_SIZE / 2;
+        let layout = Layout::new::<[u8; PAGE_SIZE]>();
+        assert_eq!(inner.alloc_zeroed(layout), Some(start + PAGE_SIZE / 2));
+
+        let bytes =
+            unsafe { core::slice::from_raw_parts(start as *const u8, heap.size()) };
+        let (before, rest) = bytes.split_at(PAGE_SIZE / 2);
+        let (allocated, after) = rest.split_at(PAGE_SIZE);
+        assert!(allocated.iter().all(|&byte| byte == 0));
+        // Only the bytes of the allocation are zeroed
+        assert!(before.iter().all(|&byte| byte == 0xFF));
+        assert!(after.iter().all(|&byte| byte == 0xFF));
+    }
+
+    #[test]
+    fn alloc_zeroed_zeroes_partially_reused_memory() {
+        let mut inner = InnerAlloc::with_page_source(EmulatedHeap::new(1));
+        dirty_and_rewind(&mut inner);
+
+        // The allocation covers the dirty bytes as well as bytes never handed out before
+        let larger = Layout::new::<[u8; 64]>();
+        let start = inner.alloc_zeroed(larger).unwrap();
+        assert_eq!(inner.untouched, start + larger.size());
+        let bytes =
+            unsafe { core::slice::from_raw_parts(start as *const u8, larger.size()) };
+        assert!(bytes.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    #[cfg(feature = "always-zero")]
+    fn alloc_zeroes_reused_memory() {
+        let mut inner = InnerAlloc::with_page_source(EmulatedHeap::new(1));
+        let layout = dirty_and_rewind(&mut inner);
+
+        let start = inner.alloc(layout).unwrap();
+        let bytes =
+            unsafe { core::slice::from_raw_parts(start as *const u8, layout.size()) };
+        assert!(bytes.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn alloc_filled_fills_the_allocation() {
+        let mut inner = InnerAlloc::with_page_source(EmulatedHeap::new(1));
+        let layout = Layout::new::<[u8; 16]>();
+
+        let ptr = inner.alloc_filled(layout, 0xA5).unwrap();
+        let bytes = unsafe { core::slice::from_raw_parts(ptr, layout.size()) };
+        assert!(bytes.iter().all(|&byte| byte == 0xA5));
+
+        // The bytes behind the allocation are left alone
+        let next = inner.alloc(Layout::new::<u8>()).unwrap();
+        assert_eq!(unsafe { *(next as *const u8) }, 0);
+    }
+
+    #[test]
+    fn alloc_filled_with_zero_skips_fresh_pages() {
+        let mut inner = InnerAlloc::with_page_source(EmulatedHeap::new(1));
+        let layout = Layout::new::<[u8; 16]>();
+
+        let ptr = inner.alloc_filled(layout, 0).unwrap();
+        // Nothing has been handed out before, so nothing had to be zeroed
+        assert_eq!(inner.untouched, ptr as usize + layout.size());
+        let bytes = unsafe { core::slice::from_raw_parts(ptr, layout.size()) };
+        assert!(bytes.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn alloc_filled_with_zero_zeroes_reused_memory() {
+        let mut inner = InnerAlloc::with_page_source(EmulatedHeap::new(1));
+        let layout = dirty_and_rewind(&mut inner);
+
+        let ptr = inner.alloc_filled(layout, 0).unwrap();
+        let bytes = unsafe { core::slice::from_raw_parts(ptr, layout.size()) };
+        assert!(bytes.iter().all(|&byte| byte == 0));
+    }
+
+    #[test]
+    fn cannot_alloc_if_aligned_start_overflows() {
+        let mut inner = InnerAlloc::from_parts(usize::MAX, usize::MAX);
+
+        assert_eq!(inner.alloc(Layout::new::<u64>()), None);
+        assert_eq!(inner.next, usize::MAX);
+        assert_eq!(inner.upper_limit, usize::MAX);
+    }
+
+    #[test]
+    fn cannot_alloc_if_alloc_end_overflows() {
+        let mut inner = InnerAlloc::from_parts(usize::MAX - 1, usize::MAX);
+
+        assert_eq!(inner.alloc(Layout::new::<[u8; 4]>()), None);
+        assert_eq!(inner.next, usize::MAX - 1);
+        assert_eq!(inner.upper_limit, usize::MAX);
+    }
+
+    #[test]
+    fn required_pages_overflow_is_caught() {
+        // A `Layout` can be at most `isize::MAX` bytes large, so on 64-bit hosts we cannot reach
+        // this case through `alloc`. On `wasm32` however the page count of a huge `Layout`
+        // plus its alignment padding may well exceed the address space.
+        assert_eq!(required_pages(usize::MAX), None);
+        assert_eq!(required_pages(usize::MAX - PAGE_SIZE + 2), None);
+    }
+
+    #[test]
+    fn required_pages_rounds_up_to_whole_pages() {
+        assert_eq!(required_pages(0), Some(0));
+        assert_eq!(required_pages(1), Some(1));
+        assert_eq!(required_pages(PAGE_SIZE - 1), Some(1));
+        assert_eq!(required_pages(PAGE_SIZE), Some(1));
+        assert_eq!(required_pages(PAGE_SIZE + 1), Some(2));
+        assert_eq!(required_pages(2 * PAGE_SIZE), Some(2));
+        assert_eq!(required_pages(2 * PAGE_SIZE + 1), Some(3));
+
+        // The largest size which does not overflow while rounding up
+        let largest = usize::MAX - (PAGE_SIZE - 1);
+        assert_eq!(required_pages(largest), Some(usize::MAX / PAGE_SIZE));
+        assert_eq!(required_pages(largest + 1), None);
+        assert_eq!(required_pages(usize::MAX), None);
+    }
+
+    /// A page source whose new pages start on the very last page of the address space.
+    struct LastPage(usize);
+
+    impl LastPage {
+        fn new() -> Self {
+            Self(usize::MAX / PAGE_SIZE)
+        }
+    }
+
+    impl PageSource for LastPage {
+        fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+            let prev_size = self.0;
+            self.0 = prev_size.checked_add(pages)?;
+            Some(prev_size)
+        }
+
+        fn memory_size(&self) -> usize {
+            self.0
+        }
+    }
+
+    /// A page source which claims to hold one page more than it hands out, so that its new pages
+    /// start a page earlier than expected.
+    #[cfg(not(feature = "init-check"))]
+    struct LyingPages(usize);
+
+    #[cfg(not(feature = "init-check"))]
+    impl PageSource for LyingPages {
+        fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+            let prev_size = self.0;
+            self.0 = prev_size.checked_add(pages)?;
+            Some(prev_size)
+        }
+
+        fn memory_size(&self) -> usize {
+            self.0 + 1
+        }
+    }
+
+    #[test]
+    // The init check traps on such a page source already
+    #[cfg(not(feature = "init-check"))]
+    fn keeps_the_pages_of_an_allocation_which_does_not_fit_them() {
+        let mut inner = InnerAlloc::with_page_source(LyingPages(0));
+
+        // The pages start at the null address instead, whose first word is reserved
+        assert_eq!(inner.alloc(Layout::new::<[u8; PAGE_SIZE]>()), None);
+        assert_eq!(inner.requested_pages, 1);
+        assert_eq!(inner.upper_limit, PAGE_SIZE);
+        #[cfg(feature = "stats")]
+        assert_eq!(inner.stats.pages_requested, 1);
+
+        // The pages have not been leaked, so later allocations are served from them
+        assert_eq!(inner.alloc(Layout::new::<u64>()), Some(FIRST));
+        assert_eq!(inner.requested_pages, 1);
+    }
+
+    #[test]
+    fn cannot_alloc_if_upper_limit_overflows() {
+        let mut inner = InnerAlloc::with_page_source(LastPage::new());
+
+        // The first page still fits into the address space...
+        assert_eq!(inner.request_pages(1), Some(usize::MAX - (PAGE_SIZE - 1)));
+
+        // ...but its end does not, so we must not accept it as our heap.
+        assert_eq!(inner.alloc(Layout::new::<u8>()), None);
+        assert_eq!(inner.next, 0);
+        assert_eq!(inner.upper_limit, 0);
+    }
+
+    /// The highest address of a heap restricted to 32-bit addresses.
+    const ADDR32_MAX: usize = u32::MAX as usize;
+
+    #[test]
+    fn addr32_catches_alloc_end_overflows() {
+        let mut inner = InnerAlloc::from_parts(ADDR32_MAX - 1, ADDR32_MAX);
+        inner.config = BYTE_EXACT.addr32();
+        let layout = Layout::new::<[u8; 4]>();
+
+        assert!(!inner.can_alloc(layout));
+        assert_eq!(inner.alloc(layout), None);
+        assert_eq!(inner.next, ADDR32_MAX - 1);
+        assert_eq!(inner.upper_limit, ADDR32_MAX);
+    }
+
+    #[test]
+    fn addr32_catches_upper_limit_overflows() {
+        let pages = Pages {
+            size: ADDR32_MAX / PAGE_SIZE,
+        };
+        let mut inner = InnerAlloc::with_config(pages, Config::DEFAULT.addr32());
+        let layout = Layout::new::<u8>();
+
+        // The last page of the 32-bit address space ends at `2^32`, just like the whole memory
+        // of a Wasm engine, which is more than a 32-bit address can hold.
+        assert!(!inner.can_alloc(layout));
+        assert_eq!(inner.alloc(layout), None);
+        assert_eq!(inner.next, 0);
+        assert_eq!(inner.upper_limit, 0);
+    }
+
+    #[test]
+    #[cfg(target_pointer_width = "64")]
+    fn addr32_diverges_from_64_bit_addresses() {
+        let heap = |config| {
+            let mut inner =
+                InnerAlloc::from_parts(ADDR32_MAX - 1, ADDR32_MAX + PAGE_SIZE);
+            inner.config = config;
+            inner
+        };
+        let layout = Layout::new::<[u8; 4]>();
+
+        assert_eq!(heap(BYTE_EXACT).alloc(layout), Some(ADDR32_MAX - 1));
+        assert_eq!(heap(BYTE_EXACT.addr32()).alloc(layout), None);
+    }
+
+    #[test]
+    fn addr32_heap_fills_up_to_the_end_of_the_address_space() {
+        // Leave room for the heap to grow by three pages before hitting the last one
+        let pages = Pages {
+            size: ADDR32_MAX / PAGE_SIZE - 3,
+        };
+        let mut inner = InnerAlloc::with_config(pages, Config::DEFAULT.addr32());
+        let layout = Layout::new::<[u8; PAGE_SIZE / 2]>();
+
+        let mut count = 0;
+        while let Some(start) = inner.alloc(layout) {
+            assert!(start + layout.size() <= ADDR32_MAX);
+            count += 1;
+        }
+        assert_eq!(count, 6);
+        assert_eq!(inner.upper_limit, ADDR32_MAX + 1 - PAGE_SIZE);
+    }
+
+    #[test]
+    fn rounds_small_allocations_up_to_granularity() {
+        let config = Config::DEFAULT.granularity(16);
+        let mut inner = InnerAlloc::with_config(Pages::new(), config);
+
+        for i in 0..4 {
+            assert_eq!(inner.alloc(Layout::new::<u8>()), Some(FIRST + i * 16));
+        }
+        assert_eq!(inner.next, FIRST + 4 * 16);
+
+        // Sizes which already are a multiple of the granularity are not rounded any further
+        assert_eq!(inner.alloc(Layout::new::<[u8; 32]>()), Some(FIRST + 4 * 16));
+        assert_eq!(inner.next, FIRST + 6 * 16);
+    }
+
+    #[test]
+    fn granularity_respects_larger_alignment() {
+        let config = Config::DEFAULT.granularity(8);
+        let mut inner = InnerAlloc::with_config(Pages::new(), config);
+
+        assert_eq!(inner.alloc(Layout::new::<u8>()), Some(FIRST));
+
+        let layout = Layout::from_size_align(4, 32).unwrap();
+        let start = inner.alloc(layout).unwrap();
+        assert_eq!(start % 32, 0);
+        assert_eq!(start, 32);
+        assert_eq!(inner.next, 64);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be a power of two")]
+    fn granularity_must_be_power_of_two() {
+        let _ = Config::DEFAULT.granularity(12);
+    }
+
+    #[test]
+    fn aligns_every_allocation_to_a_word_by_default() {
+        let mut inner = InnerAlloc::new();
+
+        for _ in 0..10 {
+            let start = inner.alloc(Layout::new::<u8>()).unwrap();
+            assert_eq!(start % size_of::<usize>(), 0);
+            assert_eq!(inner.next % size_of::<usize>(), 0);
+        }
+        assert_eq!(inner.next, FIRST + 10 * size_of::<usize>());
+
+        let start = inner.alloc(Layout::new::<[u8; 3]>()).unwrap();
+        assert_eq!(start, FIRST + 10 * size_of::<usize>());
+        assert_eq!(inner.next, FIRST + 11 * size_of::<usize>());
+    }
+
+    #[test]
+    fn min_align_is_configurable() {
+        let config = Config::DEFAULT.min_align(16);
+        let mut inner = InnerAlloc::with_config(Pages::new(), config);
+
+        // The reserved word is skipped up to the minimum alignment
+        assert_eq!(inner.alloc(Layout::new::<u8>()), Some(16));
+        assert_eq!(inner.alloc(Layout::new::<u32>()), Some(32));
+        assert_eq!(inner.next, 48);
+
+        // Larger alignments of the `Layout` are still honored
+        let layout = Layout::from_size_align(1, 64).unwrap();
+        assert_eq!(inner.alloc(layout), Some(64));
+        assert_eq!(inner.next, 128);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be a power of two")]
+    fn min_align_must_be_power_of_two() {
+        let _ = Config::DEFAULT.min_align(0);
+    }
+
+    #[test]
+    fn cannot_alloc_while_frozen() {
+        let mut bump = Bump::new(Pages::new());
+        let layout = Layout::new::<u64>();
+        assert_eq!(bump.alloc(layout), Some(FIRST));
+
+        bump.freeze();
+        assert!(bump.is_frozen());
+        assert_eq!(bump.alloc(layout), None);
+        assert_eq!(bump.alloc_zeroed(layout), None);
+        assert!(!bump.can_alloc(layout));
+        assert_eq!(bump.inner.next, FIRST + 8);
+
+        bump.thaw();
+        assert!(!bump.is_frozen());
+        assert_eq!(bump.alloc(layout), Some(FIRST + 8));
+    }
+
+    #[test]
+    fn freezing_nests() {
+        let mut bump = Bump::new(Pages::new());
+        let layout = Layout::new::<u64>();
+
+        bump.freeze();
+        bump.freeze();
+        bump.thaw();
+        assert_eq!(bump.alloc(layout), None);
+
+        bump.thaw();
+        assert_eq!(bump.alloc(layout), Some(FIRST));
+
+        // Thawing a heap which is not frozen does not freeze it the next time
+        bump.thaw();
+        bump.freeze();
+        bump.thaw();
+        assert_eq!(bump.alloc(layout), Some(FIRST + 8));
+    }
+
+    #[test]
+    #[should_panic(expected = "while the heap is frozen")]
+    fn can_trap_when_frozen() {
+        let config = Config::DEFAULT.trap_when_frozen();
+        let mut bump = Bump::with_config(Pages::new(), config);
+
+        bump.freeze();
+        let _ = bump.alloc(Layout::new::<u64>());
+    }
+
+    #[test]
+    fn trapping_when_frozen_can_be_deferred() {
+        let config = Config::DEFAULT.trap_when_frozen();
+        let mut bump = Bump::with_config(Pages::new(), config).deferring_traps();
+
+        bump.freeze();
+        assert_eq!(bump.alloc(Layout::new::<u64>()), None);
+        assert_eq!(bump.inner.trap, Some(Trap::Frozen));
+    }
+
+    #[test]
+    fn independent_bumps_do_not_interfere() {
+        let mut first = Bump::new(EmulatedHeap::new(1));
+        let mut second = Bump::new(EmulatedHeap::new(1));
+
+        let layout = Layout::new::<u64>();
+        let a = first.alloc(layout).unwrap();
+        let b = second.alloc(layout).unwrap();
+        assert_ne!(a, b);
+
+        unsafe {
+            *(a as *mut u64) = 1;
+            *(b as *mut u64) = 2;
+        }
+
+        // Allocating from the first arena does not affect the state of the second one
+        let next = second.inner.next;
+        for _ in 0..10 {
+            assert!(first.alloc(layout).is_some());
+        }
+        assert_eq!(second.inner.next, next);
+        assert_eq!(second.alloc(layout), Some(b + size_of::<u64>()));
+
+        assert_eq!(unsafe { *(a as *const u64) }, 1);
+        assert_eq!(unsafe { *(b as *const u64) }, 2);
+    }
+
+    #[test]
+    fn is_empty_until_something_is_allocated() {
+        let mut bump = Bump::new(Pages::new());
+        assert!(bump.is_empty());
+
+        let layout = Layout::new::<u64>();
+        let start = bump.alloc(layout).unwrap();
+        assert!(!bump.is_empty());
+
+        bump.dealloc(start, layout);
+        bump.reset();
+        assert!(bump.is_empty());
+    }
+
+    #[test]
+    fn reset_reuses_the_heap() {
+        let mut bump = Bump::with_config(EmulatedHeap::new(2), BYTE_EXACT);
+
+        let layout = Layout::new::<[u8; 100]>();
+        let first = bump.alloc(layout).unwrap();
+        assert_eq!(bump.alloc(layout), Some(first + 100));
+        let memory_size = bump.inner.pages.memory_size();
+
+        bump.reset();
+        assert_eq!(bump.inner.next, first);
+        assert_eq!(bump.inner.upper_limit, first + PAGE_SIZE);
+
+        // We start over from the beginning without requesting any new pages
+        assert_eq!(bump.alloc(layout), Some(first));
+        assert_eq!(bump.inner.pages.memory_size(), memory_size);
+    }
+
+    #[test]
+    fn reset_rewinds_to_the_start_of_the_heap() {
+        let mut bump = Bump::new(EmulatedHeap::new(2));
+
+        let layout = Layout::new::<u64>();
+        let first = bump.alloc(layout).unwrap();
+        assert_eq!(first, bump.inner.start);
+        assert!(bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()).is_some());
+
+        bump.reset();
+        assert_eq!(bump.alloc(layout), Some(first));
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "free-list", feature = "size-classes")))]
+    fn dealloc_does_not_release_anything() {
+        let mut bump = Bump::new(Pages::new());
+
+        let layout = Layout::new::<u64>();
+        let start = bump.alloc(layout).unwrap();
+        bump.dealloc(start, layout);
+
+        assert_eq!(bump.alloc(layout), Some(start + size_of::<u64>()));
+    }
+
+    #[test]
+    #[cfg(not(any(feature = "free-list", feature = "size-classes")))]
+    fn dealloc_leaves_the_heap_untouched() {
+        let mut bump = Bump::new(Pages::new());
+
+        let first = Layout::new::<[u8; 100]>();
+        let first_start = bump.alloc(first).unwrap();
+        let tail = Layout::new::<u64>();
+        let tail_start = bump.alloc(tail).unwrap();
+
+        let (start, next, upper_limit, untouched) = (
+            bump.inner.start,
+            bump.inner.next,
+            bump.inner.upper_limit,
+            bump.inner.untouched,
+        );
+
+        // Neither releasing the tail of the heap nor anything before it changes the heap
+        bump.dealloc(tail_start, tail);
+        bump.dealloc(first_start, first);
+
+        assert_eq!(bump.inner.start, start);
+        assert_eq!(bump.inner.next, next);
+        assert_eq!(bump.inner.upper_limit, upper_limit);
+        assert_eq!(bump.inner.untouched, untouched);
+    }
+
+    #[test]
+    #[cfg(all(feature = "free-list", not(feature = "size-classes")))]
+    fn dealloc_reclaims_memory_with_free_list() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
+
+        let layout = Layout::new::<[u8; 100]>();
+        let start = bump.alloc(layout).unwrap();
+        let next = bump.inner.next;
+
+        bump.dealloc(start, layout);
+        assert_eq!(
+            bump.inner.free.spans(),
+            &[Span {
+                start,
+                end: start + 104
+            }]
+        );
+
+        // The released memory is handed out again instead of bumping the heap
+        assert_eq!(bump.alloc(Layout::new::<[u8; 64]>()), Some(start));
+        assert_eq!(bump.inner.next, next);
+    }
+
+    #[test]
+    fn exact_fit_does_not_grow() {
+        let mut inner = InnerAlloc::from_parts(PAGE_SIZE - 8, PAGE_SIZE);
+
+        // The allocation ends exactly at the upper limit of the heap
+        assert_eq!(inner.alloc(Layout::new::<u64>()), Some(PAGE_SIZE - 8));
+        assert_eq!(inner.next, PAGE_SIZE);
+        assert_eq!(inner.upper_limit, PAGE_SIZE);
+        assert_eq!(
+            inner.pages.size, 1,
+            "No new pages should have been requested."
+        );
+    }
+
+    #[test]
+    fn one_byte_past_exact_fit_grows() {
+        let mut inner = InnerAlloc::from_parts(PAGE_SIZE - 8, PAGE_SIZE);
+        inner.config = BYTE_EXACT;
+
+        // The allocation would end one byte past the upper limit of the heap
+        assert_eq!(inner.alloc(Layout::new::<[u8; 9]>()), Some(PAGE_SIZE));
+        assert_eq!(inner.next, PAGE_SIZE + 9);
+        assert_eq!(inner.upper_limit, 2 * PAGE_SIZE);
+        assert_eq!(
+            inner.pages.size, 2,
+            "Exactly one new page should have been requested."
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn stats_reflect_activity() {
+        let mut bump = Bump::with_config(Pages::new(), BYTE_EXACT);
+        assert_eq!(bump.stats(), AllocStats::default());
+
+        assert!(bump.alloc(Layout::new::<[u8; 10]>()).is_some());
+        assert!(bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()).is_some());
+        assert!(bump.alloc(Layout::new::<u8>()).is_some());
+
+        let stats = bump.stats();
+        assert_eq!(stats.allocations, 3);
+        assert_eq!(stats.failed_allocations, 0);
+        assert_eq!(stats.bytes_allocated, 10 + PAGE_SIZE + 1);
+        assert_eq!(stats.pages_requested, 3);
+        // The second allocation did not fit into the rest of the first page
+        assert_eq!(stats.bytes_fragmented, PAGE_SIZE - FIRST - 10);
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn stats_count_failed_allocations() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
+
+        assert!(bump.alloc(Layout::new::<[u8; 2 * PAGE_SIZE]>()).is_none());
+
+        let stats = bump.stats();
+        assert_eq!(stats.allocations, 0);
+        assert_eq!(stats.failed_allocations, 1);
+        assert_eq!(stats.pages_requested, 0);
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn stats_count_deallocations() {
+        let mut bump = Bump::with_config(Pages::new(), BYTE_EXACT);
+
+        let a = bump.alloc(Layout::new::<[u8; 10]>()).unwrap();
+        let b = bump.alloc(Layout::new::<[u8; 20]>()).unwrap();
+        assert!(bump.alloc(Layout::new::<[u8; 30]>()).is_some());
+        bump.dealloc(a, Layout::new::<[u8; 10]>());
+        bump.dealloc(b, Layout::new::<[u8; 20]>());
+
+        let stats = bump.stats();
+        assert_eq!(stats.deallocations, 2);
+        assert_eq!(stats.bytes_freed, 10 + 20);
+        // The last allocation has never been released
+        assert_eq!(stats.outstanding_allocations(), 1);
+        assert_eq!(stats.outstanding_bytes(), 30);
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn clear_stats_only_resets_the_counters() {
+        let mut bump = Bump::with_config(Pages::new(), BYTE_EXACT);
+
+        assert!(bump.alloc(Layout::new::<[u8; 10]>()).is_some());
+        assert!(bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()).is_some());
+
+        let (next, upper_limit) = (bump.inner.next, bump.inner.upper_limit);
+        bump.clear_stats();
+        assert_eq!(bump.stats(), AllocStats::default());
+        assert_eq!(bump.inner.next, next);
+        assert_eq!(bump.inner.upper_limit, upper_limit);
+
+        assert!(bump.alloc(Layout::new::<[u8; 20]>()).is_some());
+        assert!(bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()).is_some());
+
+        let stats = bump.stats();
+        assert_eq!(stats.allocations, 2);
+        assert_eq!(stats.bytes_allocated, 20 + PAGE_SIZE);
+        assert_eq!(stats.pages_requested, 2);
+        assert_eq!(stats.bytes_fragmented, PAGE_SIZE - 20);
+    }
+
+    #[test]
+    #[cfg(feature = "export-metrics")]
+    fn exported_metrics_follow_the_stats() {
+        use crate::metrics::{
+            INK_HEAP_ALLOCATIONS,
+            INK_HEAP_BYTES,
+            INK_HEAP_PAGES_REQUESTED,
+        };
+        use core::sync::atomic::Ordering;
+        // No other heap of the tests exports its metrics, so nobody else writes to them
+        let mut bump = Bump::new(Pages::new()).exporting_metrics();
+        let exported = || {
+            (
+                INK_HEAP_ALLOCATIONS.load(Ordering::Relaxed),
+                INK_HEAP_BYTES.load(Ordering::Relaxed),
+                INK_HEAP_PAGES_REQUESTED.load(Ordering::Relaxed),
+            )
+        };
+
+        assert!(bump.alloc(Layout::new::<[u8; 16]>()).is_some());
+        assert!(bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()).is_some());
+        assert_eq!(exported(), (2, 16 + PAGE_SIZE, 2));
+
+        bump.clear_stats();
+        assert_eq!(exported(), (0, 0, 0));
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn stats_report_alignment_waste() {
+        let mut bump = Bump::with_config(Pages::new(), BYTE_EXACT);
+
+        // The reserved first word is skipped like any other padding
+        assert_eq!(bump.alloc(Layout::new::<u8>()), Some(FIRST));
+        // 7 bytes to get to a `u64` boundary
+        assert_eq!(bump.alloc(Layout::new::<u64>()), Some(16));
+        assert_eq!(bump.alloc(Layout::new::<u16>()), Some(24));
+        // 2 bytes to get to a `u32` boundary
+        assert_eq!(bump.alloc(Layout::new::<u32>()), Some(28));
+        // 32 bytes to get to a 64 byte boundary
+        let layout = Layout::from_size_align(1, 64).unwrap();
+        assert_eq!(bump.alloc(layout), Some(64));
+        assert_eq!(bump.stats().alignment_waste, FIRST + 7 + 2 + 32);
+
+        // The new pages start at `PAGE_SIZE`, so the whole page up to the next boundary of an
+        // alignment of two pages is skipped
+        let layout = Layout::from_size_align(1, 2 * PAGE_SIZE).unwrap();
+        assert_eq!(bump.alloc(layout), Some(2 * PAGE_SIZE));
+
+        let stats = bump.stats();
+        assert_eq!(stats.alignment_waste, FIRST + 7 + 2 + 32 + PAGE_SIZE);
+        // The tail of the first page is fragmentation, not alignment waste
+        assert_eq!(stats.bytes_fragmented, PAGE_SIZE - 128);
+    }
+
+    #[test]
+    #[cfg(feature = "debug-tags")]
+    fn stats_report_bytes_per_tag() {
+        let mut bump = Bump::with_config(Pages::new(), BYTE_EXACT);
+
+        assert!(bump
+            .alloc_tagged(Layout::new::<[u8; 10]>(), "storage")
+            .is_some());
+        assert!(bump.alloc_tagged(Layout::new::<u64>(), "events").is_some());
+        assert!(bump
+            .alloc_tagged(Layout::new::<[u8; 32]>(), "storage")
+            .is_some());
+        assert!(bump.alloc(Layout::new::<[u8; 100]>()).is_some());
+
+        let stats = bump.stats();
+        assert_eq!(stats.tags.bytes("storage"), 42);
+        assert_eq!(stats.tags.bytes("events"), 8);
+        assert_eq!(stats.tags.bytes("unknown"), 0);
+        assert_eq!(
+            stats.tags.iter().collect::<Vec<_>>(),
+            vec![("storage", 42), ("events", 8)]
+        );
+
+        // Untagged allocations only show up in the totals
+        assert_eq!(stats.bytes_allocated, 150);
+    }
+
+    #[test]
+    #[cfg(feature = "debug-tags")]
+    fn stats_accumulate_tags_beyond_capacity_as_untracked() {
+        let mut bump = Bump::with_config(Pages::new(), BYTE_EXACT);
+
+        const TAGS: [&str; MAX_TAGS + 1] = [
+            "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "10", "11", "12", "13",
+            "14", "15", "16",
+        ];
+        for tag in TAGS.iter() {
+            assert!(bump.alloc_tagged(Layout::new::<u8>(), tag).is_some());
+        }
+
+        let stats = bump.stats();
+        assert_eq!(stats.tags.iter().count(), MAX_TAGS);
+        assert_eq!(stats.tags.bytes("16"), 0);
+        assert_eq!(stats.tags.untracked(), 1);
+    }
+
+    #[test]
+    #[cfg(all(feature = "free-list", not(feature = "size-classes")))]
+    fn reuses_freed_block_for_higher_alignment() {
+        let mut inner = InnerAlloc::with_config(EmulatedHeap::new(1), BYTE_EXACT);
+
+        let base = inner.alloc(Layout::new::<u8>()).unwrap();
+        let layout = Layout::new::<[u8; 100]>();
+        assert_eq!(inner.alloc(layout), Some(base + 1));
+        inner.dealloc(base + 1, layout);
+
+        // The freed block starts at an odd address, but it still holds 32 bytes at a 32 byte
+        // boundary
+        let layout = Layout::from_size_align(32, 32).unwrap();
+        assert_eq!(inner.alloc(layout), Some(base + 32));
+        assert_eq!(inner.next, base + 101);
+
+        // Both the bytes skipped for the alignment and the tail of the block remain free
+        assert_eq!(
+            inner.free.spans(),
+            &[
+                Span {
+                    start: base + 1,
+                    end: base + 32
+                },
+                Span {
+                    start: base + 64,
+                    end: base + 101
+                },
+            ]
+        );
+
+        // The remainders are reused as well
+        assert_eq!(inner.alloc(Layout::new::<[u8; 37]>()), Some(base + 64));
+        assert_eq!(inner.alloc(Layout::new::<[u8; 31]>()), Some(base + 1));
+        assert!(inner.free.spans().is_empty());
+        assert_eq!(inner.next, base + 101);
+    }
+
+    #[test]
+    #[cfg(all(feature = "free-list", not(feature = "size-classes")))]
+    fn does_not_reuse_freed_block_which_is_too_small_once_aligned() {
+        let mut inner = InnerAlloc::with_config(EmulatedHeap::new(1), BYTE_EXACT);
+
+        let base = inner.alloc(Layout::new::<u8>()).unwrap();
+        let layout = Layout::new::<[u8; 39]>();
+        assert_eq!(inner.alloc(layout), Some(base + 1));
+        inner.dealloc(base + 1, layout);
+
+        // Aligned to 32 bytes only 8 bytes of the freed block remain, so we bump instead
+        let layout = Layout::from_size_align(16, 32).unwrap();
+        assert_eq!(inner.alloc(layout), Some(base + 64));
+        assert_eq!(
+            inner.free.spans(),
+            &[Span {
+                start: base + 1,
+                end: base + 40
+            }]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "free-list")]
+    fn reset_forgets_freed_blocks() {
+        let mut bump = Bump::new(Pages::new());
+
+        let layout = Layout::new::<[u8; 16]>();
+        let start = bump.alloc(layout).unwrap();
+        bump.dealloc(start, layout);
+        bump.reset();
+
+        assert!(bump.inner.free.spans().is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "forbid-grow")]
+    fn forbidding_grow_allows_allocations_within_the_heap() {
+        let mut bump = Bump::new(Pages::new());
+        assert!(bump.alloc(Layout::new::<u8>()).is_some());
+
+        bump.set_forbid_grow(true);
+        for _ in 0..100 {
+            assert!(bump.alloc(Layout::new::<[u8; 100]>()).is_some());
+        }
+        bump.set_forbid_grow(false);
+
+        assert_eq!(bump.inner.pages.size, 1);
+
+        // Once allowed again the heap grows as usual
+        assert!(bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()).is_some());
+        assert_eq!(bump.inner.pages.size, 2);
+    }
+
+    #[test]
+    #[cfg(feature = "forbid-grow")]
+    #[should_panic(expected = "while growing is forbidden")]
+    fn forbidding_grow_panics_on_grow() {
+        let mut bump = Bump::new(Pages::new());
+        assert!(bump.alloc(Layout::new::<u8>()).is_some());
+
+        bump.set_forbid_grow(true);
+        let _ = bump.alloc(Layout::new::<[u8; PAGE_SIZE]>());
+    }
+
+    #[test]
+    #[cfg(feature = "forbid-grow")]
+    fn forbidding_grow_can_defer_the_trap() {
+        let mut bump = Bump::new(Pages::new()).deferring_traps();
+        assert!(bump.alloc(Layout::new::<u8>()).is_some());
+
+        bump.set_forbid_grow(true);
+        assert_eq!(bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()), None);
+        assert_eq!(bump.inner.trap, Some(Trap::Grow { pages: 1 }));
+        assert_eq!(bump.inner.pages.size, 1);
+    }
+
+    #[test]
+    fn debug_output_is_human_readable() {
+        let inner = InnerAlloc::from_parts(0x3000, PAGE_SIZE);
+        assert_eq!(
+            format!("{:?}", inner),
+            "InnerAlloc { used: 12KiB, reserved: 64KiB (1 page), next: 0x3000 }"
+        );
+
+        let inner = InnerAlloc::from_parts(16 * PAGE_SIZE + 10, 17 * PAGE_SIZE);
+        assert_eq!(
+            format!("{:?}", inner),
+            "InnerAlloc { used: 1048586B, reserved: 1088KiB (17 pages), next: 0x10000a }"
+        );
+
+        let inner = InnerAlloc::new();
+        assert_eq!(
+            format!("{:?}", inner),
+            "InnerAlloc { used: 0B, reserved: 0B (0 pages), next: 0x0 }"
+        );
+    }
+
+    #[test]
+    fn realloc_shrinks_in_place() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
+
+        let layout = Layout::new::<[u8; 100]>();
+        let start = bump.alloc(layout).unwrap();
+        let next = bump.inner.next;
+
+        assert_eq!(unsafe { bump.realloc(start, layout, 40) }, Some(start));
+        assert_eq!(bump.inner.next, next);
+    }
+
+    #[test]
+    fn realloc_moves_growing_allocation() {
+        let mut bump = Bump::with_config(EmulatedHeap::new(1), BYTE_EXACT);
+
+        let layout = Layout::new::<[u8; 4]>();
+        let start = bump.alloc(layout).unwrap();
+        unsafe {
+            core::ptr::copy_nonoverlapping([1u8, 2, 3, 4].as_ptr(), start as *mut u8, 4)
+        };
+        assert!(bump.alloc(Layout::new::<u8>()).is_some());
+
+        let new_start = unsafe { bump.realloc(start, layout, 8) }.unwrap();
+        assert_eq!(new_start, start + 5);
+        let bytes = unsafe { core::slice::from_raw_parts(new_start as *const u8, 4) };
+        assert_eq!(bytes, &[1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn realloc_grows_the_tail_in_place() {
+        let mut bump = Bump::with_config(EmulatedHeap::new(1), BYTE_EXACT);
+
+        let layout = Layout::new::<[u8; 4]>();
+        let start = bump.alloc(layout).unwrap();
+        unsafe {
+            core::ptr::copy_nonoverlapping([1u8, 2, 3, 4].as_ptr(), start as *mut u8, 4)
+        };
+
+        assert_eq!(unsafe { bump.realloc(start, layout, 100) }, Some(start));
+        assert_eq!(bump.inner.next, start + 100);
+        let bytes = unsafe { core::slice::from_raw_parts(start as *const u8, 4) };
+        assert_eq!(bytes, &[1, 2, 3, 4]);
+
+        // Growing in place is an allocation as well
+        bump.freeze();
+        assert_eq!(
+            unsafe { bump.realloc(start, Layout::new::<[u8; 100]>(), 200) },
+            None
+        );
+        assert_eq!(bump.inner.next, start + 100);
+    }
+
+    #[test]
+    fn realloc_grows_the_tail_across_pages() {
+        let mut bump = Bump::new(EmulatedHeap::new(4));
+
+        let layout = Layout::new::<[u8; PAGE_SIZE / 2]>();
+        let start = bump.alloc(layout).unwrap();
+
+        // The new pages are adjacent to the heap, so the tail keeps growing into them
+        assert_eq!(
+            unsafe { bump.realloc(start, layout, 2 * PAGE_SIZE + 1) },
+            Some(start)
+        );
+        assert_eq!(bump.inner.next, start + 2 * PAGE_SIZE + 8);
+        assert_eq!(bump.inner.upper_limit, start + 3 * PAGE_SIZE);
+        assert_eq!(bump.inner.requested_pages, 3);
+
+        // Growing beyond the memory of the page source fails without moving the allocation
+        let layout = Layout::from_size_align(2 * PAGE_SIZE + 1, 1).unwrap();
+        assert_eq!(unsafe { bump.realloc(start, layout, 5 * PAGE_SIZE) }, None);
+        assert_eq!(bump.inner.next, start + 2 * PAGE_SIZE + 8);
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn growing_vec_at_the_tail_is_never_copied() {
+        let mut bump = Bump::new(EmulatedHeap::new(5));
+        let len = 20_000;
+
+        // Pushes `len` values, doubling the capacity whenever it is exhausted like `Vec` does
+        let mut layout = Layout::array::<u64>(4).unwrap();
+        let mut start = bump.alloc(layout).unwrap();
+        for i in 0..len {
+            if i * size_of::<u64>() == layout.size() {
+                let new_layout = Layout::array::<u64>(2 * i).unwrap();
+                start =
+                    unsafe { bump.realloc(start, layout, new_layout.size()) }.unwrap();
+                layout = new_layout;
+            }
+            unsafe { (start as *mut u64).add(i).write(i as u64) };
+        }
+
+        assert_eq!(bump.stats().bytes_copied, 0);
+        assert!(bump.stats().pages_requested > 1);
+        let values = unsafe { core::slice::from_raw_parts(start as *const u64, len) };
+        assert!(values.iter().copied().eq(0..len as u64));
+    }
+
+    #[test]
+    #[cfg(feature = "stats")]
+    fn realloc_copies_allocations_behind_the_tail() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
+
+        let layout = Layout::new::<[u8; 24]>();
+        let start = bump.alloc(layout).unwrap();
+        assert!(bump.alloc(Layout::new::<u8>()).is_some());
+
+        let new_start = unsafe { bump.realloc(start, layout, 48) }.unwrap();
+        assert_ne!(new_start, start);
+        assert_eq!(bump.stats().bytes_copied, 24);
+    }
+
+    #[test]
+    #[cfg(feature = "free-list")]
+    fn realloc_shrink_returns_tail_to_free_list() {
+        let mut bump = Bump::with_config(EmulatedHeap::new(1), BYTE_EXACT);
+
+        let layout = Layout::new::<[u8; 100]>();
+        let start = bump.alloc(layout).unwrap();
+        // Another allocation after ours, so that we are not the tail of the heap
+        assert!(bump.alloc(Layout::new::<u8>()).is_some());
+
+        assert_eq!(unsafe { bump.realloc(start, layout, 40) }, Some(start));
+        assert_eq!(
+            bump.inner.free.spans(),
+            &[Span {
+                start: start + 40,
+                end: start + 100
+            }]
+        );
+
+        // The reclaimed bytes are handed out again
+        let next = bump.inner.next;
+        assert_eq!(bump.alloc(Layout::new::<[u8; 60]>()), Some(start + 40));
+        assert_eq!(bump.inner.next, next);
+    }
+
+    #[test]
+    #[cfg(all(feature = "free-list", not(feature = "size-classes")))]
+    fn realloc_grow_frees_the_old_allocation() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
+
+        let layout = Layout::new::<[u8; 16]>();
+        let start = bump.alloc(layout).unwrap();
+        // Another allocation after ours, so that we are not the tail of the heap
+        assert!(bump.alloc(Layout::new::<u64>()).is_some());
+
+        let new_start = unsafe { bump.realloc(start, layout, 32) }.unwrap();
+        assert_eq!(new_start, start + 24);
+        assert_eq!(
+            bump.inner.free.spans(),
+            &[Span {
+                start,
+                end: start + 16
+            }]
+        );
+    }
+
+    #[test]
+    fn owns_exactly_the_reserved_memory() {
+        let mut bump = Bump::new(EmulatedHeap::new(2));
+        assert!(!bump.owns(core::ptr::null_mut()));
+
+        let start = bump.alloc(Layout::new::<u8>()).unwrap();
+        let upper_limit = start + PAGE_SIZE;
+        assert_eq!(bump.inner.upper_limit, upper_limit);
+
+        assert!(!bump.owns((start - 1) as *mut u8));
+        assert!(bump.owns(start as *mut u8));
+        // Memory which has been reserved but not handed out yet belongs to us as well
+        assert!(bump.owns((upper_limit - 1) as *mut u8));
+        assert!(!bump.owns(upper_limit as *mut u8));
+        assert!(!bump.owns((upper_limit + PAGE_SIZE) as *mut u8));
+    }
+
+    #[test]
+    fn resync_claims_externally_grown_pages() {
+        let mut bump = Bump::with_config(Pages::new(), BYTE_EXACT);
+        assert_eq!(bump.alloc(Layout::new::<u8>()), Some(FIRST));
+
+        // Someone else grows the memory behind our back
+        assert_eq!(bump.inner.pages.memory_grow(2), Some(1));
+
+        bump.resync();
+        assert_eq!(bump.inner.upper_limit, 3 * PAGE_SIZE);
+        assert_eq!(bump.inner.next, FIRST + 1);
+        assert_eq!(bump.inner.requested_pages, 3);
+        #[cfg(feature = "stats")]
+        assert_eq!(bump.stats().pages_requested, 3);
+
+        // The claimed pages are used without growing the memory any further
+        assert_eq!(
+            bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()),
+            Some(FIRST + 1)
+        );
+        assert_eq!(bump.inner.pages.size, 3);
+    }
+
+    #[test]
+    fn resync_claims_no_more_than_max_pages() {
+        let mut bump = Bump::with_config(Pages::new(), BYTE_EXACT.max_pages(2));
+        assert_eq!(bump.alloc(Layout::new::<u8>()), Some(FIRST));
+        assert_eq!(bump.inner.pages.memory_grow(3), Some(1));
+
+        // Only one of the new pages fits within the maximum
+        bump.resync();
+        assert_eq!(bump.inner.upper_limit, 2 * PAGE_SIZE);
+        assert_eq!(bump.inner.requested_pages, 2);
+        #[cfg(feature = "stats")]
+        assert_eq!(bump.stats().pages_requested, 2);
+
+        // The heap cannot grow any further, neither by itself nor by claiming more pages
+        assert_eq!(bump.alloc(Layout::new::<[u8; 2 * PAGE_SIZE]>()), None);
+        bump.resync();
+        assert_eq!(bump.inner.upper_limit, 2 * PAGE_SIZE);
+        assert_eq!(bump.inner.pages.size, 4);
+    }
+
+    #[test]
+    fn resync_never_shrinks_the_heap() {
+        let mut bump = Bump::new(Pages::new());
+        assert!(bump
+            .alloc(Layout::new::<[u8; 2 * PAGE_SIZE - FIRST]>())
+            .is_some());
+
+        bump.inner.pages.size = 1;
+        bump.resync();
+        assert_eq!(bump.inner.upper_limit, 2 * PAGE_SIZE);
+        assert_eq!(bump.inner.next, 2 * PAGE_SIZE);
+    }
+
+    #[test]
+    fn resync_does_not_claim_memory_without_a_heap() {
+        let mut bump = Bump::new(Pages::new());
+
+        // The memory might e.g. hold the static data of the contract
+        assert_eq!(bump.inner.pages.memory_grow(1), Some(0));
+
+        bump.resync();
+        assert_eq!(bump.inner.upper_limit, 0);
+    }
+
+    #[test]
+    fn try_grow_pages_reserves_pages_up_front() {
+        let mut bump = Bump::with_config(Pages::new(), BYTE_EXACT);
+
+        assert_eq!(bump.try_grow_pages(2), Ok(2 * PAGE_SIZE));
+        assert_eq!(bump.inner.pages.size, 2);
+        assert_eq!(bump.inner.next, 0);
+
+        // The reserved pages are used without growing the memory any further
+        let layout = Layout::new::<[u8; 2 * PAGE_SIZE - FIRST]>();
+        assert_eq!(bump.alloc(layout), Some(FIRST));
+        assert_eq!(bump.inner.pages.size, 2);
+
+        // Adjacent pages extend the heap
+        assert_eq!(bump.try_grow_pages(1), Ok(3 * PAGE_SIZE));
+        assert_eq!(bump.inner.next, 2 * PAGE_SIZE);
+        assert_eq!(bump.try_grow_pages(0), Ok(3 * PAGE_SIZE));
+    }
+
+    #[test]
+    fn try_grow_pages_stays_within_max_pages() {
+        let config = Config::DEFAULT.max_pages(2);
+        let mut bump = Bump::with_config(Pages::new(), config);
+
+        assert_eq!(bump.try_grow_pages(3), Err(AllocError::OutOfMemory));
+        assert_eq!(bump.inner.pages.size, 0);
+
+        assert_eq!(bump.try_grow_pages(2), Ok(2 * PAGE_SIZE));
+        assert_eq!(bump.try_grow_pages(1), Err(AllocError::OutOfMemory));
+        assert_eq!(bump.inner.upper_limit, 2 * PAGE_SIZE);
+    }
+
+    #[test]
+    fn try_grow_pages_rejects_overflowing_limits() {
+        let mut bump = Bump::new(LastPage::new());
+
+        // The end of the new page does not fit into the address space, so it is not requested
+        assert_eq!(bump.try_grow_pages(1), Err(AllocError::OutOfMemory));
+        assert_eq!(bump.inner.requested_pages, 0);
+        assert_eq!(bump.inner.upper_limit, 0);
+    }
+
+    #[test]
+    fn can_alloc_values() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
+
+        #[derive(Debug, PartialEq)]
+        struct Point {
+            x: u32,
+            y: i64,
+        }
+
+        let byte = bump.alloc_value(42u8).unwrap();
+        let word = bump.alloc_value(u64::MAX).unwrap();
+        let point = bump.alloc_value(Point { x: 1, y: -2 }).unwrap();
+        let array = bump.alloc_value([1u16, 2, 3]).unwrap();
+        let unit = bump.alloc_value(()).unwrap();
+
+        assert_eq!(word as usize % core::mem::align_of::<u64>(), 0);
+        assert_eq!(point as usize % core::mem::align_of::<Point>(), 0);
+        unsafe {
+            assert_eq!(*byte, 42);
+            assert_eq!(*word, u64::MAX);
+            assert_eq!(*point, Point { x: 1, y: -2 });
+            assert_eq!(*array, [1, 2, 3]);
+            assert_eq!(*unit, ());
+        }
+    }
+
+    #[test]
+    fn cannot_alloc_value_without_memory() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
+
+        assert!(bump.alloc_value([0u8; 2 * PAGE_SIZE]).is_none());
+    }
+
+    #[test]
+    fn max_pages_limits_the_heap() {
+        let config = Config::DEFAULT.max_pages(2);
+        let mut bump = Bump::with_config(Pages::new(), config);
+
+        assert!(bump
+            .alloc(Layout::new::<[u8; PAGE_SIZE - FIRST]>())
+            .is_some());
+        assert!(bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()).is_some());
+        assert_eq!(bump.alloc(Layout::new::<u8>()), None);
+        assert_eq!(bump.inner.pages.size, 2);
+    }
+
+    #[test]
+    fn can_alloc_exactly_max_pages() {
+        const MAX_PAGES: usize = 3;
+        let config = Config::DEFAULT.max_pages(MAX_PAGES);
+        // The memory already holds a page of data, so the heap starts past the null address and
+        // can use all of its pages
+        let mut bump = Bump::with_config(Pages::with_base(PAGE_SIZE), config);
+
+        let layout = Layout::new::<[u8; MAX_PAGES * PAGE_SIZE]>();
+        assert!(bump.can_alloc(layout));
+        assert_eq!(bump.alloc(layout), Some(PAGE_SIZE));
+        assert_eq!(bump.inner.pages.size, 1 + MAX_PAGES);
+        // Only empty allocations still fit
+        let empty = Layout::new::<()>();
+        assert_eq!(bump.alloc(empty), Some(empty.align()));
+        assert_eq!(bump.alloc(Layout::new::<u8>()), None);
+    }
+
+    #[test]
+    fn cannot_alloc_a_byte_more_than_max_pages() {
+        const MAX_PAGES: usize = 3;
+        let config = Config::DEFAULT.max_pages(MAX_PAGES);
+        let mut bump = Bump::with_config(Pages::with_base(PAGE_SIZE), config);
+
+        let layout = Layout::new::<[u8; MAX_PAGES * PAGE_SIZE + 1]>();
+        assert!(!bump.can_alloc(layout));
+        assert_eq!(bump.alloc(layout), None);
+        // Nothing has been requested for the failed allocation
+        assert_eq!(bump.inner.pages.size, 1);
+        assert!(bump
+            .alloc(Layout::new::<[u8; MAX_PAGES * PAGE_SIZE]>())
+            .is_some());
+    }
+
+    #[test]
+    fn can_alloc_near_max_pages() {
+        let config = Config::DEFAULT.max_pages(2);
+        let mut bump = Bump::with_config(Pages::new(), config);
+
+        assert!(bump.can_alloc(Layout::new::<[u8; 2 * PAGE_SIZE - FIRST]>()));
+        assert!(!bump.can_alloc(Layout::new::<[u8; 2 * PAGE_SIZE - FIRST + 1]>()));
+
+        assert!(bump
+            .alloc(Layout::new::<[u8; PAGE_SIZE - FIRST - 8]>())
+            .is_some());
+        // The rest of the first page fits without growing...
+        assert!(bump.can_alloc(Layout::new::<[u8; 8]>()));
+        // ...and a whole page fits into the one page left, but more than that does not
+        assert!(bump.can_alloc(Layout::new::<[u8; PAGE_SIZE]>()));
+        assert!(!bump.can_alloc(Layout::new::<[u8; PAGE_SIZE + 1]>()));
+        assert!(!bump.can_alloc(Layout::from_size_align(1, 2 * PAGE_SIZE).unwrap()));
+
+        // Probing never changes the heap
+        assert_eq!(bump.inner.next, PAGE_SIZE - 8);
+        assert_eq!(bump.inner.upper_limit, PAGE_SIZE);
+        assert_eq!(bump.inner.pages.size, 1);
+
+        // The probes agree with the actual allocations
+        assert_eq!(bump.alloc(Layout::new::<[u8; PAGE_SIZE + 1]>()), None);
+        assert!(bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()).is_some());
+    }
+
+    #[test]
+    fn cannot_alloc_if_sizes_overflow() {
+        let inner = InnerAlloc::from_parts(usize::MAX - 1, usize::MAX);
+        assert!(!inner.can_alloc(Layout::new::<[u8; 4]>()));
+        assert!(!inner.can_alloc(Layout::new::<u64>()));
+
+        // Half of the address space is in use already, so the largest possible `Layout` cannot
+        // fit into the rest of it
+        let half = isize::MAX as usize + 1;
+        let inner = InnerAlloc::from_parts(half, half);
+        let huge = Layout::from_size_align(isize::MAX as usize, 1).unwrap();
+        assert!(!inner.can_alloc(huge));
+
+        // The address of the new pages would overflow
+        let inner = InnerAlloc::with_page_source(HugePages);
+        assert!(!inner.can_alloc(Layout::new::<u8>()));
+    }
+
+    /// An event observed by the [`Recorder`].
+    #[cfg(feature = "observer")]
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Alloc(usize, Layout),
+        Dealloc(usize, Layout),
+        Grow(usize, usize),
+        Oom(Layout),
+    }
+
+    /// Records every event of the allocator it observes.
+    #[cfg(feature = "observer")]
+    #[derive(Default)]
+    struct Recorder {
+        events: std::cell::RefCell<Vec<Event>>,
+    }
+
+    #[cfg(feature = "observer")]
+    impl AllocObserver for Recorder {
+        fn on_alloc(&self, start: usize, layout: Layout) {
+            self.events.borrow_mut().push(Event::Alloc(start, layout));
+        }
+
+        fn on_dealloc(&self, start: usize, layout: Layout) {
+            self.events.borrow_mut().push(Event::Dealloc(start, layout));
+        }
+
+        fn on_grow(&self, page_start: usize, pages: usize) {
+            self.events
+                .borrow_mut()
+                .push(Event::Grow(page_start, pages));
+        }
+
+        fn on_oom(&self, layout: Layout) {
+            self.events.borrow_mut().push(Event::Oom(layout));
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "observer")]
+    fn observer_records_full_event_trace() {
+        let recorder: &'static Recorder = Box::leak(Box::new(Recorder::default()));
+        let mut bump = Bump::new(Pages::new());
+        bump.set_observer(recorder);
+
+        let small = Layout::new::<u64>();
+        let large = Layout::new::<[u8; PAGE_SIZE]>();
+        assert_eq!(bump.alloc(small), Some(FIRST));
+        bump.dealloc(FIRST, small);
+        assert_eq!(bump.alloc(large), Some(PAGE_SIZE));
+
+        bump.inner.config = Config::DEFAULT.max_pages(2);
+        assert_eq!(bump.alloc(large), None);
+        // Allocations refused while frozen are not out of memory
+        bump.freeze();
+        assert_eq!(bump.alloc(small), None);
+
+        assert_eq!(
+            *recorder.events.borrow(),
+            vec![
+                Event::Grow(0, 1),
+                Event::Alloc(FIRST, small),
+                Event::Dealloc(FIRST, small),
+                Event::Grow(PAGE_SIZE, 1),
+                Event::Alloc(PAGE_SIZE, large),
+                Event::Oom(large),
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "observer")]
+    fn observer_defaults_to_no_ops() {
+        struct Silent;
+        impl AllocObserver for Silent {}
+
+        let mut bump = Bump::new(Pages::new());
+        bump.set_observer(&Silent);
+        let layout = Layout::new::<u64>();
+        let start = bump.alloc(layout).unwrap();
+        bump.dealloc(start, layout);
+    }
+
+    #[test]
+    fn alloc_aligned_after_odd_sized_allocations() {
+        let mut bump = Bump::with_config(Pages::new(), BYTE_EXACT);
+
+        for &align in &[32, 64, 128] {
+            assert!(bump.alloc(Layout::new::<[u8; 3]>()).is_some());
+            let odd = bump.inner.next;
+            assert_eq!(odd % 2, 1, "The heap is not at an odd address.");
+
+            let ptr = bump.alloc_aligned(align, align).unwrap();
+            assert_eq!(ptr as usize % align, 0);
+            assert!(ptr as usize > odd);
+            assert_eq!(bump.inner.next, ptr as usize + align);
+        }
+    }
+
+    #[test]
+    fn alloc_aligned_rejects_invalid_requests() {
+        let mut bump = Bump::new(Pages::new());
+
+        for &align in &[0, 3, 6, 48] {
+            assert_eq!(
+                bump.alloc_aligned(8, align),
+                Err(AllocError::InvalidAlign(align))
+            );
+        }
+        // The size would overflow once rounded up to the alignment
+        assert_eq!(
+            bump.alloc_aligned(usize::MAX - 2, 4),
+            Err(AllocError::SizeOverflow)
+        );
+
+        assert_eq!(bump.inner.next, 0);
+        assert_eq!(bump.inner.upper_limit, 0);
+    }
+
+    #[test]
+    fn dropping_a_scope_restores_the_parent() {
+        let mut parent = Bump::new(EmulatedHeap::new(2));
+        let layout = Layout::new::<[u8; 64]>();
+        let first = parent.alloc(layout).unwrap();
+
+        {
+            let mut child = parent.scope();
+            assert_eq!(child.alloc(layout), Some(first + 64));
+            assert_eq!(
+                child.alloc(Layout::new::<[u8; PAGE_SIZE]>()),
+                Some(first + PAGE_SIZE)
+            );
+            assert_eq!(child.inner.next, first + 2 * PAGE_SIZE);
+        }
+
+        // The pages requested by the child stay with the parent
+        assert_eq!(parent.inner.next, first + 64);
+        assert_eq!(parent.inner.upper_limit, first + 2 * PAGE_SIZE);
+        assert_eq!(parent.alloc(layout), Some(first + 64));
+    }
+
+    #[test]
+    fn scopes_nest() {
+        let mut parent = Bump::new(EmulatedHeap::new(1));
+        let layout = Layout::new::<u64>();
+
+        let mut outer = parent.scope();
+        let first = outer.alloc(layout).unwrap();
+        {
+            let mut inner = outer.scope();
+            assert_eq!(inner.alloc(layout), Some(first + 8));
+            assert_eq!(inner.alloc(layout), Some(first + 16));
+        }
+        assert_eq!(outer.alloc(layout), Some(first + 8));
+        drop(outer);
+
+        assert_eq!(parent.inner.next, parent.inner.start);
+        assert_eq!(parent.alloc(layout), Some(first));
+    }
+
+    /// A page source which leaves a gap of one page in front of every grow, as if someone else
+    /// had grown the memory in between.
+    struct GappyPages(EmulatedHeap);
+
+    impl PageSource for GappyPages {
+        fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+            let prev_size = self.0.memory_grow(pages.checked_add(1)?)?;
+            Some(prev_size + 1)
+        }
+
+        fn memory_size(&self) -> usize {
+            self.0.memory_size()
+        }
+    }
+
+    #[test]
+    fn dropping_a_scope_rewinds_a_moved_heap() {
+        let mut parent = Bump::new(GappyPages(EmulatedHeap::new(4)));
+        let layout = Layout::new::<[u8; PAGE_SIZE - FIRST]>();
+        let first = parent.alloc(layout).unwrap();
+
+        {
+            let mut child = parent.scope();
+            // The new page is not adjacent to the heap, so the heap moves on to it
+            assert_eq!(child.alloc(layout), Some(first + 2 * PAGE_SIZE));
+            assert_eq!(child.inner.start, first + 2 * PAGE_SIZE);
+        }
+
+        // Everything on the new page belonged to the child
+        assert_eq!(parent.inner.next, first + 2 * PAGE_SIZE);
+        assert_eq!(parent.alloc(layout), Some(first + 2 * PAGE_SIZE));
+    }
+
+    #[test]
+    #[cfg(all(feature = "free-list", not(feature = "size-classes")))]
+    fn dropping_a_scope_forgets_memory_freed_within() {
+        let mut parent = Bump::new(Pages::new());
+        let layout = Layout::new::<u64>();
+        let start = parent.alloc(layout).unwrap();
+        parent.dealloc(start, layout);
+
+        {
+            let mut child = parent.scope();
+            let start = child.alloc(Layout::new::<[u8; 64]>()).unwrap();
+            child.dealloc(start, Layout::new::<[u8; 64]>());
+        }
+
+        // Only memory freed before the scope was opened can be reused
+        assert_eq!(
+            parent.inner.free.spans(),
+            &[Span {
+                start,
+                end: start + 8
+            }]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "free-list")]
+    fn dropping_a_scope_frees_memory_reused_within() {
+        let mut parent = Bump::new(EmulatedHeap::new(1));
+        // Too large for any size class, so it is freed to the free list
+        let layout = Layout::new::<[u64; 128]>();
+        let start = parent.alloc(layout).unwrap();
+        parent.dealloc(start, layout);
+
+        {
+            let mut child = parent.scope();
+            assert_eq!(child.alloc(layout), Some(start));
+        }
+
+        // The memory reused within the scope is free again
+        assert_eq!(
+            parent.inner.free.spans(),
+            &[Span {
+                start,
+                end: start + layout.size()
+            }]
+        );
+        assert_eq!(parent.alloc(layout), Some(start));
+    }
+
+    #[test]
+    #[cfg(feature = "size-classes")]
+    fn dropping_a_scope_frees_blocks_reused_within() {
+        let mut parent = Bump::new(EmulatedHeap::new(1));
+        let layout = Layout::new::<u64>();
+        let start = parent.alloc(layout).unwrap();
+        parent.dealloc(start, layout);
+
+        {
+            let mut child = parent.scope();
+            assert_eq!(child.alloc(layout), Some(start));
+            let block = child.alloc(layout).unwrap();
+            child.dealloc(block, layout);
+        }
+
+        // Only the block freed before the scope was opened is free again
+        assert_eq!(parent.inner.classes.blocks(8), &[start]);
+        assert_eq!(parent.alloc(layout), Some(start));
+    }
+
+    #[test]
+    fn failing_first_grow_leaves_the_heap_untouched() {
+        let mut pages = FailingPages::new(Pages::new());
+        pages.fail_next();
+        let mut bump = Bump::new(pages);
+        let layout = Layout::new::<u64>();
+
+        assert_eq!(bump.alloc(layout), None);
+        assert_eq!(bump.inner.next, 0);
+        assert_eq!(bump.inner.upper_limit, 0);
+        assert_eq!(bump.inner.requested_pages, 0);
+
+        // The failure was a one-off
+        assert_eq!(bump.alloc(layout), Some(FIRST));
+        assert_eq!(bump.inner.upper_limit, PAGE_SIZE);
+    }
+
+    #[test]
+    fn failing_later_grow_leaves_the_heap_untouched() {
+        let mut pages = FailingPages::new(Pages::new());
+        pages.fail_nth(3);
+        let mut bump = Bump::new(pages);
+        let layout = Layout::new::<[u8; PAGE_SIZE]>();
+        let first = Layout::new::<[u8; PAGE_SIZE - FIRST]>();
+        let small = Layout::new::<u64>();
+
+        assert_eq!(bump.alloc(first), Some(FIRST));
+        assert_eq!(bump.alloc(small), Some(PAGE_SIZE));
+        // Allocations which fit into the heap do not grow it
+        assert_eq!(bump.alloc(small), Some(PAGE_SIZE + 8));
+
+        assert_eq!(bump.alloc(layout), None);
+        assert_eq!(bump.inner.start, 0);
+        assert_eq!(bump.inner.next, PAGE_SIZE + 16);
+        assert_eq!(bump.inner.upper_limit, 2 * PAGE_SIZE);
+        assert_eq!(bump.inner.requested_pages, 2);
+
+        assert_eq!(bump.alloc(layout), Some(2 * PAGE_SIZE));
+        assert_eq!(bump.inner.upper_limit, 3 * PAGE_SIZE);
+    }
+
+    #[test]
+    #[cfg(feature = "observer")]
+    fn failing_grow_reports_oom() {
+        let recorder: &'static Recorder = Box::leak(Box::new(Recorder::default()));
+        let mut pages = FailingPages::new(Pages::new());
+        pages.fail_nth(2);
+        let mut bump = Bump::new(pages);
+        bump.set_observer(recorder);
+
+        let layout = Layout::new::<[u8; PAGE_SIZE]>();
+        let first = Layout::new::<[u8; PAGE_SIZE - FIRST]>();
+        assert_eq!(bump.alloc(first), Some(FIRST));
+        assert_eq!(bump.alloc(layout), None);
+
+        assert_eq!(
+            *recorder.events.borrow(),
+            vec![
+                Event::Grow(0, 1),
+                Event::Alloc(FIRST, first),
+                Event::Oom(layout),
+            ]
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "grows are counted starting at 1")]
+    fn failing_zeroth_grow_is_rejected() {
+        FailingPages::new(Pages::new()).fail_nth(0);
+    }
+
+    #[test]
+    fn bulk_alloc_allocates_all_layouts() {
+        let mut bump = Bump::new(Pages::new());
+        let layouts = [
+            Layout::new::<u8>(),
+            Layout::new::<[u64; 4]>(),
+            Layout::new::<[u8; PAGE_SIZE]>(),
+        ];
+
+        assert_eq!(
+            bump.bulk_alloc(&layouts),
+            Some(vec![FIRST, FIRST + 8, PAGE_SIZE])
+        );
+        assert_eq!(bump.inner.next, 2 * PAGE_SIZE);
+    }
+
+    #[test]
+    fn bulk_alloc_rolls_back_on_oom() {
+        // The memory is reused after the rollback, so it must be backed by real memory
+        let mut bump = Bump::new(EmulatedHeap::new(2));
+        let small = Layout::new::<u64>();
+        let base = bump.alloc(small).unwrap();
+
+        let layouts = [
+            small,
+            Layout::new::<[u8; PAGE_SIZE]>(),
+            Layout::new::<[u8; PAGE_SIZE]>(),
+        ];
+        assert_eq!(bump.bulk_alloc(&layouts), None);
+
+        // The page requested for the second `Layout` stays with the heap
+        assert_eq!(bump.inner.next, base + 8);
+        assert_eq!(bump.inner.upper_limit, base + 2 * PAGE_SIZE);
+        assert_eq!(bump.alloc(small), Some(base + 8));
+    }
+
+    #[test]
+    #[cfg(all(feature = "free-list", not(feature = "size-classes")))]
+    fn bulk_alloc_restores_the_free_list_on_oom() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
+        let small = Layout::new::<u64>();
+        let start = bump.alloc(small).unwrap();
+        bump.dealloc(start, small);
+
+        let layouts = [small, Layout::new::<[u8; PAGE_SIZE]>()];
+        assert_eq!(bump.bulk_alloc(&layouts), None);
+        assert_eq!(
+            bump.inner.free.spans(),
+            &[Span {
+                start,
+                end: start + 8
+            }]
+        );
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc-budget", feature = "stats"))]
+    fn bulk_alloc_which_does_not_fit_leaves_no_trace() {
+        let config = Config::DEFAULT.max_pages(2);
+        let mut bump = Bump::with_config(EmulatedHeap::new(4), config);
+        bump.set_alloc_budget(4 * PAGE_SIZE);
+        let small = Layout::new::<u64>();
+        bump.alloc(small).unwrap();
+        let (next, untouched, upper_limit) = (
+            bump.inner.next,
+            bump.inner.untouched,
+            bump.inner.upper_limit,
+        );
+        let (requested_pages, budget, stats) = (
+            bump.inner.requested_pages,
+            bump.inner.budget,
+            bump.inner.stats,
+        );
+
+        // The second `Layout` fits into a new page, but the third one exceeds the maximum
+        // number of pages
+        let layouts = [
+            small,
+            Layout::new::<[u8; PAGE_SIZE]>(),
+            Layout::new::<[u8; PAGE_SIZE]>(),
+        ];
+        assert_eq!(bump.bulk_alloc(&layouts), None);
+
+        assert_eq!(bump.inner.next, next);
+        assert_eq!(bump.inner.untouched, untouched);
+        assert_eq!(bump.inner.upper_limit, upper_limit);
+        assert_eq!(bump.inner.requested_pages, requested_pages);
+        assert_eq!(bump.inner.budget, budget);
+        assert_eq!(bump.inner.stats, stats);
+    }
+
+    #[test]
+    #[cfg(all(feature = "alloc-budget", feature = "stats"))]
+    fn bulk_alloc_refused_by_the_page_source_only_keeps_the_granted_pages() {
+        let mut bump = Bump::new(EmulatedHeap::new(2));
+        bump.set_alloc_budget(4 * PAGE_SIZE);
+        let small = Layout::new::<u64>();
+        bump.alloc(small).unwrap();
+        let (next, untouched) = (bump.inner.next, bump.inner.untouched);
+        let (requested_pages, budget, stats) = (
+            bump.inner.requested_pages,
+            bump.inner.budget,
+            bump.inner.stats,
+        );
+
+        let layouts = [
+            small,
+            Layout::new::<[u8; PAGE_SIZE]>(),
+            Layout::new::<[u8; PAGE_SIZE]>(),
+        ];
+        assert_eq!(bump.bulk_alloc(&layouts), None);
+
+        // The page granted for the second `Layout` stays with the heap
+        assert_eq!(bump.inner.next, next);
+        assert_eq!(bump.inner.untouched, untouched);
+        assert_eq!(bump.inner.budget, budget);
+        assert_eq!(bump.inner.requested_pages, requested_pages + 1);
+        assert_eq!(
+            bump.inner.stats,
+            AllocStats {
+                pages_requested: stats.pages_requested + 1,
+                ..stats
+            }
+        );
+    }
+
+    #[test]
+    fn bulk_alloc_of_nothing_succeeds() {
+        let mut bump = Bump::new(Pages::new());
+
+        assert_eq!(bump.bulk_alloc(&[]), Some(vec![]));
+        assert_eq!(bump.inner.next, 0);
+        assert_eq!(bump.inner.upper_limit, 0);
+    }
+
+    #[test]
+    #[cfg(feature = "debug-log")]
+    fn event_log_records_state_changes() {
+        let mut bump = Bump::new(Pages::new());
+        let layout = Layout::new::<[u8; 64]>();
+        let start = bump.alloc(layout).unwrap();
+        unsafe { bump.realloc(start, layout, 16) };
+        bump.dealloc(start, Layout::new::<[u8; 16]>());
+        bump.freeze();
+        assert_eq!(bump.alloc(layout), None);
+
+        assert_eq!(
+            bump.event_log().events(),
+            &[
+                AllocEvent::Grow {
+                    pages: 1,
+                    granted: true
+                },
+                AllocEvent::Alloc {
+                    layout,
+                    start: Some(start)
+                },
+                AllocEvent::Resize {
+                    start,
+                    layout,
+                    new_size: 16
+                },
+                AllocEvent::Dealloc {
+                    start,
+                    layout: Layout::new::<[u8; 16]>()
+                },
+            ]
+        );
+        assert_eq!(bump.event_log().dropped(), 0);
+    }
+
+    /// Runs a fixed workload on `bump`, stopping at the first allocation which fails.
+    ///
+    /// The workload reuses freed memory with some features, so it needs real memory.
+    #[cfg(feature = "debug-log")]
+    fn run_workload<P: PageSource>(bump: &mut Bump<P>) {
+        let small = Layout::new::<[u64; 4]>();
+        let large = Layout::new::<[u8; PAGE_SIZE]>();
+        for _ in 0..3 {
+            let start = match (bump.alloc(small), bump.alloc(large)) {
+                (Some(start), Some(_)) => start,
+                _ => return,
+            };
+            bump.dealloc(start, small);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "debug-log")]
+    fn replay_of_a_healthy_log_completes() {
+        let mut bump = Bump::new(EmulatedHeap::new(8));
+        run_workload(&mut bump);
+
+        assert_eq!(replay(bump.event_log().events()), ReplayResult::Completed);
+    }
+
+    #[test]
+    #[cfg(feature = "debug-log")]
+    fn replay_pinpoints_an_injected_oom() {
+        let mut pages = FailingPages::new(EmulatedHeap::new(8));
+        pages.fail_nth(3);
+        let mut bump = Bump::new(pages);
+        run_workload(&mut bump);
+
+        let events = bump.event_log().events();
+        let (index, layout) = events
+            .iter()
+            .enumerate()
+            .find_map(|(index, event)| {
+                match *event {
+                    AllocEvent::Alloc {
+                        layout,
+                        start: None,
+                    } => Some((index, layout)),
+                    _ => None,
+                }
+            })
+            .unwrap();
+
+        let result = replay(events);
+        assert_eq!(result, ReplayResult::OutOfMemory { index, layout });
+        // The allocation failed since the third grow has been refused
+        assert_eq!(
+            events[index - 1],
+            AllocEvent::Grow {
+                pages: 1,
+                granted: false
+            }
+        );
+        // Replaying is deterministic
+        assert_eq!(replay(events), result);
+    }
+
+    #[test]
+    #[cfg(feature = "debug-log")]
+    fn replay_detects_diverging_logs() {
+        let layout = Layout::new::<u64>();
+
+        let unknown = [AllocEvent::Dealloc { start: 8, layout }];
+        assert_eq!(
+            replay(&unknown),
+            ReplayResult::Violation {
+                index: 0,
+                reason: "the released memory is not allocated"
+            }
+        );
+
+        // The log claims success without the heap ever having grown
+        let ungrown = [AllocEvent::Alloc {
+            layout,
+            start: Some(0),
+        }];
+        assert_eq!(
+            replay(&ungrown),
+            ReplayResult::Violation {
+                index: 0,
+                reason: "the allocation failed unlike in the log"
+            }
+        );
+    }
+
+    #[test]
+    fn grows_by_the_configured_increment() {
+        let config = Config::DEFAULT.grow_increment(4);
+        let mut inner = InnerAlloc::with_config(Pages::new(), config);
+        let layout = Layout::new::<[u8; PAGE_SIZE]>();
+
+        assert_eq!(
+            inner.alloc(Layout::new::<[u8; PAGE_SIZE - FIRST]>()),
+            Some(FIRST)
+        );
+        assert_eq!(inner.upper_limit, 4 * PAGE_SIZE);
+        assert_eq!(inner.requested_pages, 4);
+
+        // The pages of the increment are used before growing again
+        for page in 1..4 {
+            assert_eq!(inner.alloc(layout), Some(page * PAGE_SIZE));
+        }
+        assert_eq!(inner.requested_pages, 4);
+        assert_eq!(inner.alloc(layout), Some(4 * PAGE_SIZE));
+        assert_eq!(inner.upper_limit, 8 * PAGE_SIZE);
+        assert_eq!(inner.pages.size, 8);
+    }
+
+    #[test]
+    fn large_allocations_exceed_the_grow_increment() {
+        let config = Config::DEFAULT.grow_increment(2);
+        let mut inner = InnerAlloc::with_config(Pages::new(), config);
+        let layout = Layout::new::<[u8; 3 * PAGE_SIZE - FIRST]>();
+
+        assert_eq!(inner.alloc(layout), Some(FIRST));
+        assert_eq!(inner.upper_limit, 3 * PAGE_SIZE);
+        assert_eq!(inner.next, 3 * PAGE_SIZE);
+    }
 
-use core::alloc::{
-    GlobalAlloc,
-    Layout,
-};
+    #[test]
+    fn grow_increment_stays_within_max_pages() {
+        let config = Config::DEFAULT.grow_increment(4).max_pages(6);
+        let mut inner = InnerAlloc::with_config(Pages::new(), config);
+        let layout = Layout::new::<[u8; PAGE_SIZE]>();
 
-/// A page in Wasm is `64KiB`
-const PAGE_SIZE: usize = 64 * 1024;
+        assert_eq!(inner.alloc(layout), Some(FIRST));
+        assert_eq!(inner.upper_limit, 4 * PAGE_SIZE);
+        inner.next = inner.upper_limit;
 
-static mut INNER: InnerAlloc = InnerAlloc::new();
+        // Only two pages are left, which is less than the increment
+        assert!(inner.can_alloc(layout));
+        assert_eq!(inner.alloc(layout), Some(4 * PAGE_SIZE));
+        assert_eq!(inner.upper_limit, 6 * PAGE_SIZE);
+        assert_eq!(inner.requested_pages, 6);
 
-/// A bump allocator suitable for use in a Wasm environment.
-pub struct BumpAllocator;
+        inner.next = inner.upper_limit;
+        assert!(!inner.can_alloc(layout));
+        assert_eq!(inner.alloc(layout), None);
+    }
 
-unsafe impl GlobalAlloc for BumpAllocator {
-    #[inline]
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        match INNER.alloc(layout) {
-            Some(start) => start as *mut u8,
-            None => core::ptr::null_mut(),
-        }
+    #[test]
+    #[cfg(feature = "stats")]
+    fn stats_count_the_pages_of_the_increment() {
+        let mut bump = Bump::with_config(Pages::new(), Config::DEFAULT.grow_increment(3));
+        bump.alloc(Layout::new::<u8>()).unwrap();
+
+        assert_eq!(bump.stats().pages_requested, 3);
     }
 
-    #[inline]
-    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
-        // A new page in Wasm is guaranteed to already be zero initialized, so we can just use our
-        // regular `alloc` call here and save a bit of work.
-        //
-        // See: https://webassembly.github.io/spec/core/exec/modules.html#growing-memories
-        self.alloc(layout)
+    #[test]
+    #[should_panic(expected = "the grow increment must not be zero")]
+    fn grow_increment_must_not_be_zero() {
+        let _ = Config::DEFAULT.grow_increment(0);
     }
 
-    #[inline]
-    unsafe fn dealloc(&self, _ptr: *mut u8, _layout: Layout) {}
-}
+    #[test]
+    fn never_hands_out_the_null_address() {
+        let mut bump = Bump::new(Pages::new());
 
-#[cfg_attr(feature = "std", derive(Debug, Copy, Clone))]
-struct InnerAlloc {
-    /// Points to the start of the next available allocation.
-    next: usize,
+        // Empty allocations are dangling, but not at the null address either
+        let empty = Layout::new::<()>();
+        assert!(bump.can_alloc(empty));
+        assert_eq!(bump.alloc(empty), Some(empty.align()));
 
-    /// The address of the upper limit of our heap.
-    upper_limit: usize,
-}
+        // The null address is page aligned, so the allocation moves on to the next page
+        let page_aligned = Layout::from_size_align(8, PAGE_SIZE).unwrap();
+        assert!(bump.can_alloc(page_aligned));
+        assert_eq!(bump.alloc(page_aligned), Some(PAGE_SIZE));
+    }
 
-impl InnerAlloc {
-    const fn new() -> Self {
-        Self {
-            next: 0,
-            upper_limit: 0,
-        }
+    #[test]
+    fn empty_allocations_with_large_alignment_consume_nothing() {
+        let mut bump = Bump::new(Pages::new());
+        let empty = Layout::from_size_align(0, 4096).unwrap();
+
+        // Not even the first page is requested for them
+        assert!(bump.can_alloc(empty));
+        assert_eq!(bump.alloc(empty), Some(4096));
+        assert_eq!(bump.inner.pages.size, 0);
+
+        assert_eq!(bump.alloc(Layout::new::<u64>()), Some(FIRST));
+        let next = bump.inner.next;
+        let start = bump.alloc(empty).unwrap();
+        assert_eq!(start % 4096, 0);
+        assert_eq!(bump.inner.next, next);
+        assert_eq!(bump.inner.pages.size, 1);
+
+        // Releasing them does not hand out their dangling address either
+        bump.dealloc(start, empty);
+        assert_eq!(bump.alloc(Layout::new::<u64>()), Some(next));
     }
 
-    cfg_if::cfg_if! {
-        if #[cfg(test)] {
-            /// Request a `pages` number of page sized sections of Wasm memory. Each page is `64KiB` in size.
-            ///
-            /// Returns `None` if a page is not available.
-            ///
-            /// This implementation is only meant to be used for testing, since we cannot (easily)
-            /// test the `wasm32` implementation.
-            fn request_pages(&mut self, _pages: usize) -> Option<usize> {
-                Some(self.upper_limit)
-            }
-        } else if #[cfg(feature = "std")] {
-            fn request_pages(&mut self, _pages: usize) -> Option<usize> {
-                unreachable!(
-                    "This branch is only used to keep the compiler happy when building tests, and
-                     should never actually be called outside of a test run."
-                )
-            }
-        } else if #[cfg(target_arch = "wasm32")] {
-            /// Request a `pages` number of pages of Wasm memory. Each page is `64KiB` in size.
-            ///
-            /// Returns `None` if a page is not available.
-            fn request_pages(&mut self, pages: usize) -> Option<usize> {
-                let prev_page = core::arch::wasm32::memory_grow(0, pages);
-                if prev_page == usize::MAX {
-                    return None;
-                }
+    #[test]
+    fn rewinding_never_hands_out_the_null_address() {
+        let mut bump = Bump::with_config(EmulatedHeap::new(1), BYTE_EXACT);
+        let first = bump.alloc(Layout::new::<u8>()).unwrap();
+        assert_ne!(first, 0);
 
-                prev_page.checked_mul(PAGE_SIZE)
-            }
-        } else {
-            compile_error! {
-                "ink! only supports compilation as `std` or `no_std` + `wasm32-unknown`"
-            }
-        }
+        bump.reset();
+        assert_eq!(bump.inner.next, bump.inner.start);
+        assert_eq!(bump.alloc(Layout::new::<u8>()), Some(first));
+
+        bump.reset();
+        assert_eq!(bump.scope().alloc(Layout::new::<u8>()), Some(first));
+        assert_eq!(bump.alloc(Layout::new::<u8>()), Some(first));
     }
 
-    /// Tries to allocate enough memory on the heap for the given `Layout`. If there is not enough
-    /// room on the heap it'll try and grow it by a page.
-    ///
-    /// Note: This implementation results in internal fragmentation when allocating across pages.
-    fn alloc(&mut self, layout: Layout) -> Option<usize> {
-        let alloc_start = self.next;
+    #[test]
+    #[cfg(feature = "size-classes")]
+    fn size_classes_reuse_freed_blocks_of_the_same_class() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
 
-        let aligned_size = layout.pad_to_align().size();
-        let alloc_end = alloc_start.checked_add(aligned_size)?;
+        let layout = Layout::new::<[u8; 100]>();
+        let start = bump.alloc(layout).unwrap();
+        let next = bump.inner.next;
+        // The allocation is not rounded up to its size class
+        assert_eq!(next, start + 104);
+        bump.dealloc(start, layout);
 
-        if alloc_end > self.upper_limit {
-            let required_pages = required_pages(aligned_size)?;
-            let page_start = self.request_pages(required_pages)?;
+        // Any allocation of the same class which fits reuses the block without bumping the heap
+        assert_eq!(bump.alloc(Layout::new::<[u8; 65]>()), Some(start));
+        assert_eq!(bump.inner.next, next);
+        assert!(bump.inner.classes.blocks(128).is_empty());
+        // The rest of the block is binned on its own
+        assert_eq!(bump.inner.classes.blocks(32), &[start + 72]);
+    }
 
-            self.upper_limit = required_pages
-                .checked_mul(PAGE_SIZE)
-                .and_then(|pages| page_start.checked_add(pages))?;
-            self.next = page_start.checked_add(aligned_size)?;
+    #[test]
+    #[cfg(feature = "size-classes")]
+    fn size_classes_fall_back_to_bumping_across_classes() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
 
-            Some(page_start)
-        } else {
-            self.next = alloc_end;
-            Some(alloc_start)
-        }
+        let first = bump.alloc(Layout::new::<u64>()).unwrap();
+        let layout = Layout::new::<[u8; 64]>();
+        let start = bump.alloc(layout).unwrap();
+        assert_eq!(start, first + 8);
+        bump.dealloc(start, layout);
+
+        assert_eq!(bump.alloc(Layout::new::<[u8; 100]>()), Some(start + 64));
+        assert_eq!(bump.alloc(Layout::new::<[u8; 32]>()), Some(start + 168));
+        // The block is kept for its own class
+        assert_eq!(bump.inner.classes.blocks(64), &[start]);
+
+        // A block which is not aligned well enough is not reused either
+        let over_aligned = Layout::from_size_align(64, 16).unwrap();
+        assert_eq!(bump.alloc(over_aligned), Some(start + 200));
+        assert_eq!(bump.alloc(layout), Some(start));
     }
-}
 
-/// Calculates the number of pages of memory needed for an allocation of `size` bytes.
-///
-/// This function rounds up to the next page. For example, if we have an allocation of
-/// `size = PAGE_SIZE / 2` this function will indicate that one page is required to satisfy
-/// the allocation.
-#[inline]
-fn required_pages(size: usize) -> Option<usize> {
-    size.checked_add(PAGE_SIZE - 1)
-        .and_then(|num| num.checked_div(PAGE_SIZE))
-}
+    #[test]
+    #[cfg(all(feature = "size-classes", not(feature = "free-list")))]
+    fn size_classes_do_not_bin_large_allocations() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::mem::size_of;
+        let layout = Layout::new::<[u8; 1000]>();
+        let start = bump.alloc(layout).unwrap();
+        assert_eq!(bump.inner.next, start + 1000);
+        bump.dealloc(start, layout);
+
+        assert_eq!(bump.alloc(layout), Some(start + 1000));
+    }
 
     #[test]
-    fn can_alloc_no_bytes() {
-        let mut inner = InnerAlloc::new();
+    #[cfg(feature = "debug-tracking")]
+    fn memory_map_lists_live_allocations_by_address() {
+        let mut bump = Bump::new(Pages::new());
+
+        let small = Layout::new::<u64>();
+        let large = Layout::new::<[u8; PAGE_SIZE]>();
+        let first = bump.alloc(small).unwrap();
+        let freed = bump.alloc(Layout::new::<[u8; 100]>()).unwrap();
+        let page = bump.alloc(large).unwrap();
+        let last = bump.alloc(Layout::new::<u8>()).unwrap();
+        bump.dealloc(freed, Layout::new::<[u8; 100]>());
 
-        let layout = Layout::new::<()>();
-        assert_eq!(inner.alloc(layout), Some(0));
+        assert_eq!(
+            bump.memory_map(),
+            vec![(first, 8, None), (page, PAGE_SIZE, None), (last, 1, None)]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "debug-tracking")]
+    fn memory_map_leaves_out_empty_allocations() {
+        let mut bump = Bump::new(Pages::new());
 
-        let expected_limit =
-            PAGE_SIZE * required_pages(layout.pad_to_align().size()).unwrap();
-        assert_eq!(inner.upper_limit, expected_limit);
+        let empty = Layout::from_size_align(0, 64).unwrap();
+        let start = bump.alloc(Layout::new::<u64>()).unwrap();
+        assert_eq!(bump.alloc(empty), Some(64));
+        bump.dealloc(64, empty);
 
-        let expected_alloc_start = size_of::<()>();
-        assert_eq!(inner.next, expected_alloc_start);
+        assert_eq!(bump.memory_map(), vec![(start, 8, None)]);
+        assert_eq!(bump.untracked_allocations(), 0);
     }
 
     #[test]
-    fn can_alloc_a_byte() {
-        let mut inner = InnerAlloc::new();
+    #[cfg(all(feature = "debug-tracking", feature = "debug-tags"))]
+    fn memory_map_lists_the_tags() {
+        let mut bump = Bump::new(Pages::new());
 
-        let layout = Layout::new::<u8>();
-        assert_eq!(inner.alloc(layout), Some(0));
+        let layout = Layout::new::<[u8; 32]>();
+        let tagged = bump.alloc_tagged(layout, "storage").unwrap();
+        let untagged = bump.alloc(layout).unwrap();
 
-        let expected_limit =
-            PAGE_SIZE * required_pages(layout.pad_to_align().size()).unwrap();
-        assert_eq!(inner.upper_limit, expected_limit);
+        assert_eq!(
+            bump.memory_map(),
+            vec![(tagged, 32, Some("storage")), (untagged, 32, None)]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "debug-tracking")]
+    fn memory_map_follows_reallocs_and_resets() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
+
+        let layout = Layout::new::<[u8; 64]>();
+        let moved = bump.alloc(layout).unwrap();
+        let shrunk = bump.alloc(layout).unwrap();
+        assert_eq!(unsafe { bump.realloc(shrunk, layout, 16) }, Some(shrunk));
+        let new_start = unsafe { bump.realloc(moved, layout, 128) }.unwrap();
+
+        assert_eq!(
+            bump.memory_map(),
+            vec![(shrunk, 16, None), (new_start, 128, None)]
+        );
+
+        bump.reset();
+        assert!(bump.memory_map().is_empty());
+    }
+
+    /// A model of the bump allocator which computes every allocation from scratch in wide
+    /// arithmetic, without any of the shortcuts of [`InnerAlloc`].
+    ///
+    /// Freed memory is never reused, so the model does not hold with the `free-list` or
+    /// `size-classes` features.
+    #[cfg(not(any(feature = "free-list", feature = "size-classes")))]
+    #[derive(Clone)]
+    struct Reference {
+        next: u128,
+        upper_limit: u128,
+        max_pages: u128,
+    }
+
+    #[cfg(not(any(feature = "free-list", feature = "size-classes")))]
+    impl Reference {
+        fn alloc(&mut self, layout: Layout) -> Option<usize> {
+            let round_up = |value: u128, to: u128| value.div_ceil(to) * to;
+            let page = PAGE_SIZE as u128;
+            let align = core::cmp::max(layout.align(), size_of::<usize>()) as u128;
+            let size = round_up(layout.size() as u128, align);
+
+            // Empty allocations are dangling
+            if layout.size() == 0 {
+                return Some(layout.align())
+            }
+
+            let start = round_up(core::cmp::max(self.next, NULL_GUARD as u128), align);
+            if start + size <= self.upper_limit {
+                self.next = start + size;
+                return Some(start as usize)
+            }
+
+            // The heap grows by enough pages for the worst case padding of the allocation, or
+            // more if the reserved first word pushes it beyond them
+            let start =
+                round_up(core::cmp::max(self.upper_limit, NULL_GUARD as u128), align);
+            let padding = align.saturating_sub(page);
+            let pages = core::cmp::max(
+                round_up(size + padding, page),
+                round_up(start + size - self.upper_limit, page),
+            ) / page;
+            if self.upper_limit / page + pages > self.max_pages {
+                return None
+            }
+            self.upper_limit += pages * page;
+            self.next = start + size;
+            Some(start as usize)
+        }
+    }
+
+    /// A xorshift generator, so that the random tests are reproducible.
+    #[cfg(not(any(feature = "free-list", feature = "size-classes")))]
+    struct XorShift(u64);
 
-        let expected_alloc_start = size_of::<u8>();
-        assert_eq!(inner.next, expected_alloc_start);
+    #[cfg(not(any(feature = "free-list", feature = "size-classes")))]
+    impl XorShift {
+        fn next(&mut self) -> usize {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0 as usize
+        }
     }
 
     #[test]
-    fn can_alloc_a_foobarbaz() {
-        let mut inner = InnerAlloc::new();
+    #[cfg(not(any(feature = "free-list", feature = "size-classes")))]
+    fn matches_the_reference_model() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        let fresh = |rng: &mut XorShift| {
+            let max_pages = 1 + rng.next() % 16;
+            let config = Config::DEFAULT.max_pages(max_pages);
+            let reference = Reference {
+                next: 0,
+                upper_limit: 0,
+                max_pages: max_pages as u128,
+            };
+            (InnerAlloc::with_config(Pages::new(), config), reference)
+        };
+        let (mut inner, mut reference) = fresh(&mut rng);
 
-        struct FooBarBaz {
-            _foo: u32,
-            _bar: u128,
-            _baz: (u16, bool),
+        for step in 0..100_000 {
+            let size = match rng.next() % 4 {
+                0 => rng.next() % 64,
+                1 => rng.next() % PAGE_SIZE,
+                2 => rng.next() % (3 * PAGE_SIZE),
+                _ => (1 + rng.next() % 2) * PAGE_SIZE - rng.next() % 16,
+            };
+            let align = 1 << (rng.next() % 18);
+            let layout = Layout::from_size_align(size, align).unwrap();
+
+            let expected = reference.clone().alloc(layout);
+            assert_eq!(
+                inner.can_alloc(layout),
+                expected.is_some(),
+                "step {}: {:?}",
+                step,
+                layout
+            );
+            if rng.next().is_multiple_of(4) {
+                // Only probe the heap this time
+                continue
+            }
+
+            let expected = reference.alloc(layout);
+            assert_eq!(inner.alloc(layout), expected, "step {}: {:?}", step, layout);
+            assert_eq!(inner.next as u128, reference.next, "step {}", step);
+            assert_eq!(
+                inner.upper_limit as u128, reference.upper_limit,
+                "step {}",
+                step
+            );
+            match expected {
+                Some(start) => inner.dealloc(start, layout),
+                // Start over every now and then, so that the heap keeps growing
+                None if rng.next().is_multiple_of(4) => {
+                    (inner, reference) = fresh(&mut rng)
+                }
+                None => {}
+            }
         }
+    }
+
+    /// A page source whose memory is already so large that the address of any new page overflows.
+    struct HugePages;
 
-        let layout = Layout::new::<FooBarBaz>();
-        let mut total_size = 0;
+    impl PageSource for HugePages {
+        fn memory_grow(&mut self, _pages: usize) -> Option<usize> {
+            Some(usize::MAX / 2)
+        }
 
-        let allocations = 3;
-        for _ in 0..allocations {
-            assert!(inner.alloc(layout).is_some());
-            total_size += layout.pad_to_align().size();
+        fn memory_size(&self) -> usize {
+            usize::MAX / 2
         }
+    }
+
+    #[test]
+    #[cfg(feature = "alloc-budget")]
+    fn exhausted_budget_runs_out_of_memory() {
+        let mut bump = Bump::new(Pages::new());
+        bump.set_alloc_budget(100);
+
+        let layout = Layout::new::<[u8; 64]>();
+        assert_eq!(bump.alloc(layout), Some(FIRST));
+        assert_eq!(bump.alloc_budget(), Some(36));
+
+        // There is plenty of room left on the page, but not in the budget
+        assert!(!bump.can_alloc(layout));
+        assert_eq!(bump.alloc(layout), None);
+        assert_eq!(bump.inner.next, FIRST + 64);
+        assert_eq!(bump.inner.upper_limit, PAGE_SIZE);
 
-        let expected_limit = PAGE_SIZE * required_pages(total_size).unwrap();
-        assert_eq!(inner.upper_limit, expected_limit);
+        // Allocations are charged with their padding
+        assert_eq!(bump.alloc(Layout::new::<u8>()), Some(FIRST + 64));
+        assert_eq!(bump.alloc_budget(), Some(28));
 
-        let expected_alloc_start = allocations * size_of::<FooBarBaz>();
-        assert_eq!(inner.next, expected_alloc_start);
+        // Releasing memory does not refund the budget
+        bump.dealloc(FIRST, layout);
+        assert_eq!(bump.alloc_budget(), Some(28));
+        assert_eq!(bump.alloc(Layout::new::<[u8; 32]>()), None);
     }
 
     #[test]
-    fn can_alloc_across_pages() {
-        let mut inner = InnerAlloc::new();
+    #[cfg(feature = "alloc-budget")]
+    fn resetting_the_budget_allows_allocations_again() {
+        let mut bump = Bump::new(Pages::new());
+        bump.set_alloc_budget(8);
 
-        struct Foo {
-            _foo: [u8; PAGE_SIZE - 1],
-        }
+        let layout = Layout::new::<u64>();
+        assert!(bump.alloc(layout).is_some());
+        assert_eq!(bump.alloc(layout), None);
+
+        bump.set_alloc_budget(PAGE_SIZE);
+        assert!(bump.alloc(layout).is_some());
+        assert_eq!(bump.alloc_budget(), Some(PAGE_SIZE - 8));
+    }
+
+    #[test]
+    #[cfg(feature = "alloc-budget")]
+    fn budget_limits_growing_in_place() {
+        let mut bump = Bump::new(EmulatedHeap::new(1));
+        bump.set_alloc_budget(64);
 
-        // First, let's allocate a struct which is _almost_ a full page
-        let layout = Layout::new::<Foo>();
-        assert_eq!(inner.alloc(layout), Some(0));
+        let layout = Layout::new::<[u8; 16]>();
+        let start = bump.alloc(layout).unwrap();
+        assert_eq!(unsafe { bump.realloc(start, layout, 80) }, None);
+        assert_eq!(bump.inner.next, start + 16);
 
-        let expected_limit =
-            PAGE_SIZE * required_pages(layout.pad_to_align().size()).unwrap();
-        assert_eq!(inner.upper_limit, expected_limit);
+        assert_eq!(unsafe { bump.realloc(start, layout, 64) }, Some(start));
+        assert_eq!(bump.alloc_budget(), Some(0));
+    }
 
-        let expected_alloc_start = size_of::<Foo>();
-        assert_eq!(inner.next, expected_alloc_start);
+    #[test]
+    #[cfg(feature = "init-check")]
+    fn init_check_passes_on_a_clean_heap() {
+        Bump::new(Pages::new()).check_init();
 
-        // Now we'll allocate two bytes which will push us over to the next page
-        let layout = Layout::new::<u16>();
-        assert_eq!(inner.alloc(layout), Some(PAGE_SIZE));
+        let mut bump = Bump::new(EmulatedHeap::new(2));
+        bump.check_init();
+        // Growing the heap checks the page size of the page source
+        assert!(bump.alloc(Layout::new::<[u8; PAGE_SIZE]>()).is_some());
+    }
 
-        let expected_limit = 2 * PAGE_SIZE;
-        assert_eq!(inner.upper_limit, expected_limit);
+    #[test]
+    // The check for earlier allocations is only made in debug builds
+    #[cfg(all(feature = "init-check", debug_assertions))]
+    #[should_panic(expected = "the allocator has been used before its initialization")]
+    fn init_check_trips_after_an_allocation() {
+        let mut bump = Bump::new(Pages::new());
+        // Something allocates before the contract gets to set up the allocator
+        bump.alloc(Layout::new::<u8>()).unwrap();
 
-        // Notice that we start the allocation on the second page, instead of making use of the
-        // remaining byte on the first page
-        let expected_alloc_start = PAGE_SIZE + size_of::<u16>();
-        assert_eq!(inner.next, expected_alloc_start);
+        bump.check_init();
     }
 
     #[test]
-    fn can_alloc_multiple_pages() {
-        let mut inner = InnerAlloc::new();
+    // The check for earlier allocations is only made in debug builds
+    #[cfg(all(feature = "init-check", debug_assertions))]
+    #[should_panic(expected = "the allocator has been used before its initialization")]
+    fn init_check_trips_after_a_reset() {
+        let mut bump = Bump::new(Pages::new());
+        bump.alloc(Layout::new::<u8>()).unwrap();
+        bump.reset();
 
-        struct Foo {
-            _foo: [u8; 2 * PAGE_SIZE],
-        }
+        bump.check_init();
+    }
+
+    #[test]
+    #[cfg(feature = "init-check")]
+    #[should_panic(expected = "the pages of the memory are not 65536 bytes large")]
+    fn init_check_trips_on_mismatching_pages() {
+        /// A page source whose pages are only half as large as ours, so that it needs twice as
+        /// many of them for the same memory.
+        struct HalfPages(usize);
+
+        impl PageSource for HalfPages {
+            fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+                let prev_size = self.0;
+                self.0 += 2 * pages;
+                Some(prev_size)
+            }
 
-        let layout = Layout::new::<Foo>();
-        assert_eq!(inner.alloc(layout), Some(0));
+            fn memory_size(&self) -> usize {
+                self.0
+            }
+        }
 
-        let expected_limit =
-            PAGE_SIZE * required_pages(layout.pad_to_align().size()).unwrap();
-        assert_eq!(inner.upper_limit, expected_limit);
+        let mut bump = Bump::new(HalfPages(0));
+        bump.check_init();
+        let _ = bump.alloc(Layout::new::<u8>());
+    }
 
-        let expected_alloc_start = size_of::<Foo>();
-        assert_eq!(inner.next, expected_alloc_start);
+    #[test]
+    fn cannot_alloc_if_page_address_overflows() {
+        let mut inner = InnerAlloc::with_page_source(HugePages);
 
-        // Now we want to make sure that the state of our allocator is correct for any subsequent
-        // allocations
         let layout = Layout::new::<u8>();
-        assert_eq!(inner.alloc(layout), Some(2 * PAGE_SIZE));
+        assert_eq!(inner.alloc(layout), None);
+
+        // The failed allocation must not have left behind a bogus heap
+        assert_eq!(inner.next, 0);
+        assert_eq!(inner.upper_limit, 0);
+    }
+
+    #[test]
+    fn global_heap_can_be_shared_by_threads() {
+        let threads = (0..4)
+            .map(|_| {
+                std::thread::spawn(|| {
+                    (0..1_000)
+                        .map(|_| {
+                            // SAFETY: The layout is not empty.
+                            unsafe { BumpAllocator.alloc(Layout::new::<u64>()) as usize }
+                        })
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        let mut starts = threads
+            .into_iter()
+            .flat_map(|thread| thread.join().unwrap())
+            .collect::<Vec<_>>();
+
+        // No allocation has been handed out twice
+        let allocations = starts.len();
+        starts.sort_unstable();
+        starts.dedup();
+        assert_eq!(starts.len(), allocations);
+    }
+
+    #[test]
+    #[should_panic(expected = "the global heap has been accessed reentrantly")]
+    fn global_heap_refuses_reentrant_accesses() {
+        with_global(|_| with_global(|_| ()));
+    }
 
-        let expected_limit = 3 * PAGE_SIZE;
-        assert_eq!(inner.upper_limit, expected_limit);
+    #[test]
+    fn global_heap_traps_once_released() {
+        let trapped = std::panic::catch_unwind(|| {
+            with_global(|heap| heap.inner.trap(Trap::Frozen))
+        });
+        assert!(trapped.is_err());
 
-        let expected_alloc_start = 2 * PAGE_SIZE + size_of::<u8>();
-        assert_eq!(inner.next, expected_alloc_start);
+        // The panic did not happen while the heap was locked, so it can still be used
+        assert_eq!(with_global(|heap| heap.inner.trap), None);
     }
 }
 
 #[cfg(all(test, feature = "ink-fuzz-tests"))]
 mod fuzz_tests {
     use super::*;
+    use crate::emulated::EmulatedHeap;
     use quickcheck::{
         quickcheck,
         TestResult,
@@ -286,33 +4967,61 @@ mod fuzz_tests {
         checked that it is a PAGE_SIZE less than `usize::MAX`; Alignment is a
         non-zero, power of two.";
 
+    /// Returns the configuration of a fuzzed heap, which either uses the address width of the
+    /// host or the 32-bit addresses of Wasm.
+    fn config(addr32: bool) -> Config {
+        if addr32 {
+            Config::DEFAULT.addr32()
+        } else {
+            Config::DEFAULT
+        }
+    }
+
+    /// Returns whether the pages of a fresh heap can hold everything up to `end` without
+    /// exceeding the highest address of `inner`.
+    fn fits_into_address_space(inner: &InnerAlloc<Pages>, end: usize) -> bool {
+        required_pages(end)
+            .and_then(|pages| pages.checked_mul(PAGE_SIZE))
+            .is_some_and(|limit| limit <= inner.config.max_addr)
+    }
+
     #[quickcheck]
-    fn should_allocate_arbitrary_sized_bytes(n: usize) -> TestResult {
+    fn should_allocate_arbitrary_sized_bytes(n: usize, addr32: bool) -> TestResult {
         // If `n` is going to overflow we don't want to check it here (we'll check the overflow
         // case in another test)
         if n.checked_add(PAGE_SIZE - 1).is_none() {
             return TestResult::discard()
         }
 
-        let mut inner = InnerAlloc::new();
+        let mut inner = InnerAlloc::with_config(Pages::new(), config(addr32));
 
         let layout =
             Layout::from_size_align(n, size_of::<usize>()).expect(FROM_SIZE_ALIGN_EXPECT);
+        // Empty allocations do not need any memory
+        if let Some(start) = inner.dangling(layout) {
+            assert_eq!(inner.alloc(layout), Some(start));
+            assert_eq!(inner.next, 0, "An empty allocation moved `next`.");
+            return TestResult::passed()
+        }
 
         let size = layout.pad_to_align().size();
+        let start = align_up(NULL_GUARD, layout.align()).unwrap();
+        if !fits_into_address_space(&inner, start + size) {
+            return TestResult::discard()
+        }
         assert_eq!(
             inner.alloc(layout),
-            Some(0),
+            Some(start),
             "The given pointer for the allocation doesn't match."
         );
 
-        let expected_alloc_start = size;
+        let expected_alloc_start = start + size;
         assert_eq!(
             inner.next, expected_alloc_start,
             "Our next allocation doesn't match where it should start."
         );
 
-        let expected_limit = PAGE_SIZE * required_pages(size).unwrap();
+        let expected_limit = PAGE_SIZE * required_pages(expected_alloc_start).unwrap();
         assert_eq!(
             inner.upper_limit, expected_limit,
             "The upper bound of our heap doesn't match."
@@ -322,14 +5031,18 @@ mod fuzz_tests {
     }
 
     #[quickcheck]
-    fn should_not_allocate_arbitrary_bytes_if_they_overflow(n: usize) -> TestResult {
+    fn should_not_allocate_arbitrary_bytes_if_they_overflow(
+        n: usize,
+        addr32: bool,
+    ) -> TestResult {
+        let mut inner = InnerAlloc::with_config(Pages::new(), config(addr32));
+
         // In the previous test we ignored the overflow case, now we ignore the valid cases
-        if n.checked_add(PAGE_SIZE - 1).is_some() {
+        if fits_into_address_space(&inner, n) {
             return TestResult::discard()
         }
 
         if let Ok(layout) = Layout::from_size_align(n, size_of::<usize>()) {
-            let mut inner = InnerAlloc::new();
             assert_eq!(
                 inner.alloc(layout),
                 None,
@@ -347,6 +5060,7 @@ mod fuzz_tests {
     fn should_allocate_regardless_of_alignment_size(
         n: usize,
         align: usize,
+        addr32: bool,
     ) -> TestResult {
         let aligns = [1, 2, 4, 8, 16, 32, 64, 128, 256, 512];
         let align = aligns[align % aligns.len()];
@@ -357,23 +5071,35 @@ mod fuzz_tests {
             return TestResult::discard()
         }
 
-        let mut inner = InnerAlloc::new();
+        let mut inner = InnerAlloc::with_config(Pages::new(), config(addr32));
 
         let layout = Layout::from_size_align(n, align).expect(FROM_SIZE_ALIGN_EXPECT);
-        let size = layout.pad_to_align().size();
+        // Empty allocations do not need any memory
+        if let Some(start) = inner.dangling(layout) {
+            assert_eq!(inner.alloc(layout), Some(start));
+            assert_eq!(inner.next, 0, "An empty allocation moved `next`.");
+            return TestResult::passed()
+        }
+
+        // Small alignments are raised to the minimum alignment of the heap
+        let size = inner.allocation_size(layout).unwrap();
+        let start = align_up(NULL_GUARD, inner.alignment(layout)).unwrap();
+        if !fits_into_address_space(&inner, start + size) {
+            return TestResult::discard()
+        }
         assert_eq!(
             inner.alloc(layout),
-            Some(0),
+            Some(start),
             "The given pointer for the allocation doesn't match."
         );
 
-        let expected_alloc_start = size;
+        let expected_alloc_start = start + size;
         assert_eq!(
             inner.next, expected_alloc_start,
             "Our next allocation doesn't match where it should start."
         );
 
-        let expected_limit = PAGE_SIZE * required_pages(size).unwrap();
+        let expected_limit = PAGE_SIZE * required_pages(expected_alloc_start).unwrap();
         assert_eq!(
             inner.upper_limit, expected_limit,
             "The upper bound of our heap doesn't match."
@@ -392,8 +5118,11 @@ mod fuzz_tests {
     /// Each of the vectors represents one sequence of allocations. Within each sequence the
     /// individual size of allocations will be randomly selected by `quickcheck`.
     #[quickcheck]
-    fn should_allocate_arbitrary_byte_sequences(sequence: Vec<usize>) -> TestResult {
-        let mut inner = InnerAlloc::new();
+    fn should_allocate_arbitrary_byte_sequences(
+        sequence: Vec<usize>,
+        addr32: bool,
+    ) -> TestResult {
+        let mut inner = InnerAlloc::with_config(Pages::new(), config(addr32));
 
         if sequence.is_empty() {
             return TestResult::discard()
@@ -413,7 +5142,7 @@ mod fuzz_tests {
         let pages_required = sequence
             .iter()
             .fold(0, |acc, &x| acc + required_pages(x).unwrap());
-        let max_pages = required_pages(usize::MAX - PAGE_SIZE + 1).unwrap();
+        let max_pages = required_pages(inner.config.max_addr - PAGE_SIZE + 1).unwrap();
 
         // We know this is going to end up overflowing, we'll check this case in a different
         // test
@@ -421,8 +5150,9 @@ mod fuzz_tests {
             return TestResult::discard()
         }
 
-        let mut expected_alloc_start = 0;
-        let mut total_bytes_requested = 0;
+        // The reserved first word is skipped as if it had been allocated
+        let mut expected_alloc_start = NULL_GUARD;
+        let mut total_bytes_requested = NULL_GUARD;
         let mut total_bytes_fragmented = 0;
 
         for alloc in sequence {
@@ -430,6 +5160,14 @@ mod fuzz_tests {
                 .expect(FROM_SIZE_ALIGN_EXPECT);
             let size = layout.pad_to_align().size();
 
+            // Empty allocations are dangling and leave the heap alone
+            if let Some(start) = inner.dangling(layout) {
+                let next = inner.next;
+                assert_eq!(inner.alloc(layout), Some(start));
+                assert_eq!(inner.next, next, "An empty allocation moved `next`.");
+                continue
+            }
+
             let current_page_limit = PAGE_SIZE * required_pages(inner.next).unwrap();
             let is_too_big_for_current_page = inner.next + size > current_page_limit;
 
@@ -471,6 +5209,109 @@ mod fuzz_tests {
         TestResult::passed()
     }
 
+    /// Checks that the heap never grows by more pages than needed, i.e. that after every
+    /// allocation the reserved region covers exactly the pages required to hold everything
+    /// up to `next` (which includes the bytes lost to fragmentation).
+    ///
+    /// The allocation sizes are bounded to a few pages so that the sequences actually cross
+    /// page boundaries instead of immediately running out of address space.
+    #[quickcheck]
+    fn should_never_grow_more_pages_than_required(
+        sequence: Vec<usize>,
+        addr32: bool,
+    ) -> TestResult {
+        let mut inner = InnerAlloc::with_config(Pages::new(), config(addr32));
+
+        if sequence.is_empty() {
+            return TestResult::discard()
+        }
+
+        for alloc in sequence {
+            let size = alloc % (3 * PAGE_SIZE);
+            let layout = Layout::from_size_align(size, size_of::<usize>())
+                .expect(FROM_SIZE_ALIGN_EXPECT);
+
+            // Running out of address space is checked in other tests
+            if inner.alloc(layout).is_none() {
+                return TestResult::discard()
+            }
+
+            let expected_limit = PAGE_SIZE * required_pages(inner.next).unwrap();
+            assert_eq!(
+                inner.upper_limit, expected_limit,
+                "The heap grew by more pages than the allocations require."
+            );
+        }
+
+        TestResult::passed()
+    }
+
+    /// Puts the heap into the last few pages of the address space and checks that an allocation
+    /// either fails without touching the heap or hands out memory which lies entirely within it.
+    ///
+    /// Any wraparound in the address computations would show up as an allocation starting
+    /// before `next` or ending beyond `upper_limit`.
+    #[quickcheck]
+    fn should_never_wrap_around_near_the_end_of_the_address_space(
+        limit_offset: usize,
+        next_offset: usize,
+        size: usize,
+        align: usize,
+        addr32: bool,
+    ) -> TestResult {
+        let aligns = [
+            1,
+            2,
+            4,
+            8,
+            16,
+            32,
+            64,
+            128,
+            256,
+            512,
+            PAGE_SIZE,
+            2 * PAGE_SIZE,
+        ];
+        let align = aligns[align % aligns.len()];
+        let size = size % (4 * PAGE_SIZE);
+
+        let config = config(addr32);
+        let last_page = config.max_addr / PAGE_SIZE;
+        let upper_limit = (last_page - limit_offset % 4) * PAGE_SIZE;
+        let next = upper_limit - next_offset % (2 * PAGE_SIZE);
+        let mut inner = InnerAlloc::from_parts(next, upper_limit);
+        inner.config = config;
+
+        let layout = Layout::from_size_align(size, align).expect(FROM_SIZE_ALIGN_EXPECT);
+        match inner.alloc(layout) {
+            Some(start) if inner.dangling(layout) == Some(start) => {
+                assert_eq!(inner.next, next, "An empty allocation moved `next`.");
+                assert_eq!(start % align, 0, "An allocation is misaligned.");
+            }
+            Some(start) => {
+                let end = start
+                    .checked_add(size)
+                    .expect("An allocation wrapped around the address space.");
+                assert!(start >= next, "An allocation started before `next`.");
+                assert!(
+                    end <= inner.next && inner.next <= inner.upper_limit,
+                    "An allocation ended beyond the heap."
+                );
+                assert_eq!(start % align, 0, "An allocation is misaligned.");
+            }
+            None => {
+                assert_eq!(inner.next, next, "A failed allocation moved `next`.");
+                assert_eq!(
+                    inner.upper_limit, upper_limit,
+                    "A failed allocation moved the upper limit."
+                );
+            }
+        }
+
+        TestResult::passed()
+    }
+
     // For this test we have sequences of allocations which will eventually overflow the maximum
     // amount of pages (in practice this means our heap will be OOM).
     //
@@ -479,8 +5320,9 @@ mod fuzz_tests {
     #[quickcheck]
     fn should_not_allocate_arbitrary_byte_sequences_which_eventually_overflow(
         sequence: Vec<usize>,
+        addr32: bool,
     ) -> TestResult {
-        let mut inner = InnerAlloc::new();
+        let mut inner = InnerAlloc::with_config(Pages::new(), config(addr32));
 
         if sequence.is_empty() {
             return TestResult::discard()
@@ -500,7 +5342,7 @@ mod fuzz_tests {
         let pages_required = sequence
             .iter()
             .fold(0, |acc, &x| acc + required_pages(x).unwrap());
-        let max_pages = required_pages(usize::MAX - PAGE_SIZE + 1).unwrap();
+        let max_pages = required_pages(inner.config.max_addr - PAGE_SIZE + 1).unwrap();
 
         // We want to explicitly test for the case where a series of allocations eventually
         // runs out of pages of memory
@@ -523,4 +5365,50 @@ mod fuzz_tests {
 
         TestResult::passed()
     }
+
+    #[quickcheck]
+    fn should_never_extend_the_heap_beyond_its_limit(
+        ops: Vec<(u8, u16, u8)>,
+    ) -> TestResult {
+        // The allocations are moved by reallocations, so they need to be backed by memory
+        let mut inner = InnerAlloc::with_page_source(EmulatedHeap::new(16));
+        let mut live: Vec<(usize, Layout)> = Vec::new();
+
+        for (op, size, arg) in ops {
+            let size = size as usize;
+            match op % 3 {
+                0 => {
+                    let align = 1 << (arg % 10);
+                    let layout = Layout::from_size_align(size, align)
+                        .expect(FROM_SIZE_ALIGN_EXPECT);
+                    if let Some(start) = inner.alloc(layout) {
+                        live.push((start, layout));
+                    }
+                }
+                1 if !live.is_empty() => {
+                    let index = arg as usize % live.len();
+                    let (start, layout) = live[index];
+                    // SAFETY: The allocation is live and has been made with this layout.
+                    if let Some(new_start) = unsafe { inner.realloc(start, layout, size) }
+                    {
+                        let new_layout = Layout::from_size_align(size, layout.align())
+                            .expect(FROM_SIZE_ALIGN_EXPECT);
+                        live[index] = (new_start, new_layout);
+                    }
+                }
+                2 if !live.is_empty() => {
+                    let (start, layout) = live.swap_remove(arg as usize % live.len());
+                    inner.dealloc(start, layout);
+                }
+                _ => {}
+            }
+
+            assert!(
+                inner.next <= inner.upper_limit,
+                "The heap continues beyond the memory reserved for it."
+            );
+        }
+
+        TestResult::passed()
+    }
 }
@@ -0,0 +1,109 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! An emulation of the linear memory of a Wasm engine which is backed by real memory.
+
+use crate::bump::{
+    PageSource,
+    PAGE_SIZE,
+};
+use std::alloc::{
+    GlobalAlloc,
+    Layout,
+    System,
+};
+
+/// Emulates the linear memory of a Wasm engine on top of the allocator of the host.
+///
+/// The memory for all `capacity` pages is reserved on the first grow, so that the emulated
+/// memory never moves once pages have been handed out. Just like in Wasm, newly grown pages are
+/// zero initialized.
+///
+/// We reserve the memory directly from the [`System`] allocator, so that the emulated heap can
+/// back a global allocator without recursing into itself.
+#[derive(Debug)]
+pub struct EmulatedHeap {
+    /// The start of the reserved memory, or null if nothing has been reserved yet.
+    memory: *mut u8,
+    /// The current size of the emulated memory in pages.
+    size: usize,
+    /// The maximum number of pages the emulated memory can grow to.
+    capacity: usize,
+}
+
+impl EmulatedHeap {
+    /// Creates an emulated memory which can grow up to `capacity` pages.
+    pub const fn new(capacity: usize) -> Self {
+        Self {
+            memory: core::ptr::null_mut(),
+            size: 0,
+            capacity,
+        }
+    }
+
+    /// The layout of all the memory reserved for the emulated heap.
+    fn layout(&self) -> Option<Layout> {
+        let size = self.capacity.checked_mul(PAGE_SIZE)?;
+        Layout::from_size_align(size, PAGE_SIZE).ok()
+    }
+}
+
+impl PageSource for EmulatedHeap {
+    fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+        let new_size = self.size.checked_add(pages)?;
+        if new_size > self.capacity {
+            return None
+        }
+
+        if self.memory.is_null() {
+            let layout = self.layout()?;
+            if layout.size() == 0 {
+                return None
+            }
+            self.memory = unsafe { System.alloc(layout) };
+            if self.memory.is_null() {
+                return None
+            }
+        }
+
+        // The memory is aligned to the page size, so we can express its address in pages just
+        // like the Wasm engine does for its linear memory.
+        let prev_page = self.memory as usize / PAGE_SIZE + self.size;
+        unsafe {
+            core::ptr::write_bytes(
+                self.memory.add(self.size * PAGE_SIZE),
+                0,
+                pages * PAGE_SIZE,
+            );
+        }
+        self.size = new_size;
+
+        Some(prev_page)
+    }
+
+    fn memory_size(&self) -> usize {
+        if self.memory.is_null() {
+            return 0
+        }
+        self.memory as usize / PAGE_SIZE + self.size
+    }
+}
+
+impl Drop for EmulatedHeap {
+    fn drop(&mut self) {
+        if let (false, Some(layout)) = (self.memory.is_null(), self.layout()) {
+            unsafe { System.dealloc(self.memory, layout) }
+        }
+    }
+}
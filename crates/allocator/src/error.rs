@@ -0,0 +1,86 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Errors of the bump allocator and the validation of the requests leading to them.
+
+use core::alloc::Layout;
+
+/// The reason why a request to the allocator has been rejected.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AllocError {
+    /// The requested alignment is not a power of two, which includes an alignment of `0`.
+    InvalidAlign(usize),
+    /// The requested size overflows once it is rounded up to its alignment.
+    SizeOverflow,
+    /// The memory cannot be grown any further.
+    OutOfMemory,
+}
+
+impl core::fmt::Display for AllocError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::InvalidAlign(align) => {
+                write!(f, "the alignment {} is not a power of two", align)
+            }
+            Self::SizeOverflow => write!(f, "the size of the allocation overflows"),
+            Self::OutOfMemory => write!(f, "there is not enough memory left"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for AllocError {}
+
+/// Checks that `align` can be used as the alignment of an allocation.
+pub fn validate_align(align: usize) -> Result<(), AllocError> {
+    if !align.is_power_of_two() {
+        return Err(AllocError::InvalidAlign(align))
+    }
+    Ok(())
+}
+
+/// Builds the `Layout` of `size` bytes aligned to `align`.
+///
+/// Every helper which builds a `Layout` from its arguments goes through here, so that invalid
+/// requests are rejected the same way everywhere.
+pub(crate) fn layout(size: usize, align: usize) -> Result<Layout, AllocError> {
+    validate_align(align)?;
+    Layout::from_size_align(size, align).map_err(|_| AllocError::SizeOverflow)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accepts_powers_of_two() {
+        for shift in 0..usize::BITS {
+            assert_eq!(validate_align(1 << shift), Ok(()));
+        }
+    }
+
+    #[test]
+    fn rejects_other_alignments() {
+        for &align in &[0, 3, 6] {
+            assert_eq!(validate_align(align), Err(AllocError::InvalidAlign(align)));
+            assert_eq!(layout(8, align), Err(AllocError::InvalidAlign(align)));
+        }
+    }
+
+    #[test]
+    fn rejects_overflowing_sizes() {
+        assert_eq!(layout(usize::MAX - 2, 4), Err(AllocError::SizeOverflow));
+        assert_eq!(layout(8, 4), Ok(Layout::from_size_align(8, 4).unwrap()));
+    }
+}
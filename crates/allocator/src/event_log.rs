@@ -0,0 +1,267 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A log of the activity of the bump allocator, for reproducing bugs off-chain.
+//!
+//! Only available if the `debug-log` crate feature is enabled. The log has a fixed capacity so
+//! that it does not need any heap memory itself; events which do not fit into it any more are
+//! only counted.
+
+use core::alloc::Layout;
+
+/// The maximum number of events which are logged.
+pub const EVENT_LOG_CAPACITY: usize = 128;
+
+/// An event in the life of a heap which changed its state.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AllocEvent {
+    /// The heap requested `pages` more pages, which have been `granted` or not.
+    Grow { pages: usize, granted: bool },
+    /// An allocation of the given `Layout` has been handed out at `start`, or failed if `start`
+    /// is `None`.
+    Alloc {
+        layout: Layout,
+        start: Option<usize>,
+    },
+    /// The allocation of the given `Layout` at `start` has been released.
+    Dealloc { start: usize, layout: Layout },
+    /// The allocation of the given `Layout` at `start` has been resized to `new_size` bytes in
+    /// place.
+    Resize {
+        start: usize,
+        layout: Layout,
+        new_size: usize,
+    },
+}
+
+/// The events of a heap in the order in which they happened.
+#[derive(Debug, Copy, Clone)]
+pub struct EventLog {
+    events: [AllocEvent; EVENT_LOG_CAPACITY],
+    len: usize,
+    dropped: usize,
+}
+
+impl EventLog {
+    /// Creates an empty log.
+    pub const fn new() -> Self {
+        Self {
+            events: [AllocEvent::Grow {
+                pages: 0,
+                granted: false,
+            }; EVENT_LOG_CAPACITY],
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Returns the logged events.
+    pub fn events(&self) -> &[AllocEvent] {
+        &self.events[..self.len]
+    }
+
+    /// Returns the number of events which happened after the log was full.
+    ///
+    /// The log is only complete, and can only be replayed, if this is `0`.
+    pub fn dropped(&self) -> usize {
+        self.dropped
+    }
+
+    /// Appends `event` to the log, or counts it as dropped once the log is full.
+    pub(crate) fn record(&mut self, event: AllocEvent) {
+        if self.len == EVENT_LOG_CAPACITY {
+            self.dropped += 1;
+            return
+        }
+        self.events[self.len] = event;
+        self.len += 1;
+    }
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The outcome of replaying a log, see [`replay`].
+#[cfg(feature = "std")]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ReplayResult {
+    /// All events have been replayed as logged.
+    Completed,
+    /// The allocation of the event at `index` ran out of memory, just like it did in the log.
+    OutOfMemory { index: usize, layout: Layout },
+    /// The replay of the event at `index` broke an invariant of the allocator or diverged from
+    /// the log.
+    Violation { index: usize, reason: &'static str },
+}
+
+/// Replays `events` on a fresh heap with the default [`Config`](crate::Config) to find out
+/// where an allocation ran out of memory or the allocator went wrong.
+///
+/// The heap is emulated off-chain and grows exactly as logged, so every replay of the same
+/// events has the same outcome. The allocations get different addresses than the logged ones,
+/// which are translated for the deallocations. The replay stops at the first allocation which
+/// runs out of memory, or as soon as an invariant is broken: allocations must be aligned, must
+/// not overlap any live allocation and must succeed or fail just like they did in the log.
+#[cfg(feature = "std")]
+pub fn replay(events: &[AllocEvent]) -> ReplayResult {
+    use crate::{
+        bump::{
+            Bump,
+            PageSource,
+        },
+        emulated::EmulatedHeap,
+    };
+    use core::cell::Cell;
+
+    /// Grows the emulated heap only as the next logged grow did.
+    struct ReplayPages<'a> {
+        heap: EmulatedHeap,
+        grow: &'a Cell<Option<(usize, bool)>>,
+    }
+
+    impl PageSource for ReplayPages<'_> {
+        fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+            match self.grow.take() {
+                Some((logged, true)) if logged == pages => self.heap.memory_grow(pages),
+                _ => None,
+            }
+        }
+
+        fn memory_size(&self) -> usize {
+            self.heap.memory_size()
+        }
+    }
+
+    let violation = |index, reason| ReplayResult::Violation { index, reason };
+
+    let capacity = events
+        .iter()
+        .map(|event| {
+            match *event {
+                AllocEvent::Grow {
+                    pages,
+                    granted: true,
+                } => pages,
+                _ => 0,
+            }
+        })
+        .sum();
+    let grow = Cell::new(None);
+    let mut bump = Bump::new(ReplayPages {
+        heap: EmulatedHeap::new(capacity),
+        grow: &grow,
+    });
+    // The logged start, the replayed start and the `Layout` of every live allocation
+    let mut live: Vec<(usize, usize, Layout)> = Vec::new();
+
+    for (index, event) in events.iter().enumerate() {
+        match *event {
+            AllocEvent::Grow { pages, granted } => grow.set(Some((pages, granted))),
+            AllocEvent::Alloc { layout, start } => {
+                let replayed = bump.alloc(layout);
+                if grow.take().is_some() {
+                    return violation(index, "the heap did not grow as logged")
+                }
+                let (logged, replayed) = match (start, replayed) {
+                    (None, None) => return ReplayResult::OutOfMemory { index, layout },
+                    (Some(_), None) => {
+                        return violation(index, "the allocation failed unlike in the log")
+                    }
+                    (None, Some(_)) => {
+                        return violation(
+                            index,
+                            "the allocation succeeded unlike in the log",
+                        )
+                    }
+                    (Some(logged), Some(replayed)) => (logged, replayed),
+                };
+                if replayed % layout.align() != 0 {
+                    return violation(index, "the allocation is misaligned")
+                }
+                let end = replayed + layout.size();
+                if live.iter().any(|&(_, other, other_layout)| {
+                    replayed < other + other_layout.size() && other < end
+                }) {
+                    return violation(index, "the allocation overlaps a live allocation")
+                }
+                live.push((logged, replayed, layout));
+            }
+            AllocEvent::Dealloc { start, layout } => {
+                let position = match live.iter().position(|&(logged, _, live_layout)| {
+                    logged == start && live_layout == layout
+                }) {
+                    Some(position) => position,
+                    None => {
+                        return violation(index, "the released memory is not allocated")
+                    }
+                };
+                let (_, replayed, _) = live.swap_remove(position);
+                bump.dealloc(replayed, layout);
+            }
+            AllocEvent::Resize {
+                start,
+                layout,
+                new_size,
+            } => {
+                let entry = match live.iter_mut().find(|(logged, _, live_layout)| {
+                    *logged == start && *live_layout == layout
+                }) {
+                    Some(entry) => entry,
+                    None => {
+                        return violation(index, "the resized memory is not allocated")
+                    }
+                };
+                // SAFETY: The allocation is live, so its memory is ours to resize.
+                if unsafe { bump.realloc(entry.1, layout, new_size) } != Some(entry.1) {
+                    return violation(index, "the allocation was not resized in place")
+                }
+                entry.2 = match Layout::from_size_align(new_size, layout.align()) {
+                    Ok(new_layout) => new_layout,
+                    Err(_) => return violation(index, "the new size is invalid"),
+                };
+            }
+        }
+    }
+
+    ReplayResult::Completed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_events_beyond_capacity() {
+        let mut log = EventLog::new();
+        for pages in 0..EVENT_LOG_CAPACITY + 3 {
+            log.record(AllocEvent::Grow {
+                pages,
+                granted: true,
+            });
+        }
+
+        assert_eq!(log.events().len(), EVENT_LOG_CAPACITY);
+        assert_eq!(
+            log.events().last(),
+            Some(&AllocEvent::Grow {
+                pages: EVENT_LOG_CAPACITY - 1,
+                granted: true
+            })
+        );
+        assert_eq!(log.dropped(), 3);
+    }
+}
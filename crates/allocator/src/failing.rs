@@ -0,0 +1,76 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A page source which fails on request, for testing how code copes with running out of memory.
+
+use crate::bump::PageSource;
+
+/// Wraps another [`PageSource`] and makes a chosen grow of it fail.
+///
+/// Once the chosen grow has failed, all following grows are passed on to the wrapped source
+/// again until the next failure is requested.
+#[derive(Debug)]
+pub struct FailingPages<P> {
+    pages: P,
+    /// The number of grows left until, and including, the one which fails.
+    fail_in: Option<usize>,
+}
+
+impl<P> FailingPages<P> {
+    /// Wraps `pages` without requesting any failures yet.
+    pub const fn new(pages: P) -> Self {
+        Self {
+            pages,
+            fail_in: None,
+        }
+    }
+
+    /// Makes the next grow fail.
+    pub fn fail_next(&mut self) {
+        self.fail_nth(1)
+    }
+
+    /// Makes the `n`th grow from now fail, starting at `1` for the next one.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is zero.
+    pub fn fail_nth(&mut self, n: usize) {
+        assert!(n != 0, "grows are counted starting at 1");
+        self.fail_in = Some(n);
+    }
+}
+
+impl<P> PageSource for FailingPages<P>
+where
+    P: PageSource,
+{
+    fn memory_grow(&mut self, pages: usize) -> Option<usize> {
+        match self.fail_in {
+            Some(1) => {
+                self.fail_in = None;
+                None
+            }
+            Some(n) => {
+                self.fail_in = Some(n - 1);
+                self.pages.memory_grow(pages)
+            }
+            None => self.pages.memory_grow(pages),
+        }
+    }
+
+    fn memory_size(&self) -> usize {
+        self.pages.memory_size()
+    }
+}
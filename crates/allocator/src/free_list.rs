@@ -0,0 +1,195 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A list of freed spans of the heap which can be handed out again.
+//!
+//! Only used if the `free-list` crate feature is enabled. The list has a fixed capacity so that
+//! it does not need any heap memory itself; spans which do not fit into it any more are lost,
+//! just like every freed span is without the feature.
+
+use crate::bump::align_up;
+
+/// The maximum number of freed spans which are remembered at the same time.
+pub(crate) const FREE_LIST_CAPACITY: usize = 32;
+
+/// A span of freed memory from `start` up to, but excluding, `end`.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub(crate) struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// The freed spans of the heap, in the order in which they were freed.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct FreeList {
+    spans: [Span; FREE_LIST_CAPACITY],
+    len: usize,
+}
+
+impl FreeList {
+    /// Creates an empty free list.
+    pub const fn new() -> Self {
+        Self {
+            spans: [Span { start: 0, end: 0 }; FREE_LIST_CAPACITY],
+            len: 0,
+        }
+    }
+
+    /// Returns the spans which are currently free.
+    pub fn spans(&self) -> &[Span] {
+        &self.spans[..self.len]
+    }
+
+    /// Forgets about all free spans.
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Adds the span from `start` up to `end` to the list.
+    ///
+    /// Empty spans are ignored, and so are all spans once the list is full.
+    pub fn push(&mut self, start: usize, end: usize) {
+        if start >= end || self.len == FREE_LIST_CAPACITY {
+            return
+        }
+        self.spans[self.len] = Span { start, end };
+        self.len += 1;
+    }
+
+    /// Takes `size` bytes aligned to `align` from the first span they fit into.
+    ///
+    /// The start of a span is aligned up to `align` before checking whether the request fits,
+    /// so a span freed by an allocation of a smaller alignment can still be reused. The bytes
+    /// skipped for the alignment and the bytes left over at the end of the span are put back
+    /// into the list.
+    ///
+    /// Returns `None` for empty requests, since those never consume any memory anyway.
+    pub fn take(&mut self, size: usize, align: usize) -> Option<usize> {
+        let (index, start, end) = self.find(size, align)?;
+
+        let span = self.remove(index);
+        self.push(span.start, start);
+        self.push(end, span.end);
+
+        Some(start)
+    }
+
+    /// Returns whether `size` bytes aligned to `align` fit into any of the spans, see
+    /// [`FreeList::take`].
+    pub fn fits(&self, size: usize, align: usize) -> bool {
+        self.find(size, align).is_some()
+    }
+
+    /// Finds the first span `size` bytes aligned to `align` fit into.
+    ///
+    /// Returns the index of the span together with the start and end of the request within it.
+    fn find(&self, size: usize, align: usize) -> Option<(usize, usize, usize)> {
+        if size == 0 {
+            return None
+        }
+
+        self.spans().iter().enumerate().find_map(|(index, span)| {
+            let start = align_up(span.start, align)?;
+            let end = start.checked_add(size)?;
+            (end <= span.end).then_some((index, start, end))
+        })
+    }
+
+    /// Removes the span at `index`, keeping the order of the remaining spans.
+    fn remove(&mut self, index: usize) -> Span {
+        let span = self.spans[index];
+        self.spans.copy_within(index + 1..self.len, index);
+        self.len -= 1;
+        span
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn takes_from_the_first_fitting_span() {
+        let mut list = FreeList::new();
+        list.push(0, 8);
+        list.push(100, 132);
+        list.push(200, 232);
+
+        assert_eq!(list.take(16, 1), Some(100));
+        assert_eq!(
+            list.spans(),
+            &[
+                Span { start: 0, end: 8 },
+                Span {
+                    start: 200,
+                    end: 232
+                },
+                Span {
+                    start: 116,
+                    end: 132
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn aligns_the_start_of_a_span() {
+        let mut list = FreeList::new();
+        list.push(1, 64);
+
+        // The bytes skipped for the alignment as well as the rest of the span stay free
+        assert_eq!(list.take(16, 16), Some(16));
+        assert_eq!(
+            list.spans(),
+            &[Span { start: 1, end: 16 }, Span { start: 32, end: 64 }]
+        );
+    }
+
+    #[test]
+    fn skips_spans_which_are_too_small_once_aligned() {
+        let mut list = FreeList::new();
+        list.push(1, 24);
+
+        // The span holds 23 bytes, but only 8 of them are left after aligning to 16
+        assert_eq!(list.take(16, 16), None);
+        assert_eq!(list.spans(), &[Span { start: 1, end: 24 }]);
+    }
+
+    #[test]
+    fn ignores_empty_spans_and_requests() {
+        let mut list = FreeList::new();
+        list.push(8, 8);
+        assert!(list.spans().is_empty());
+
+        list.push(0, 8);
+        assert_eq!(list.take(0, 1), None);
+        assert_eq!(list.spans(), &[Span { start: 0, end: 8 }]);
+    }
+
+    #[test]
+    fn forgets_spans_beyond_capacity() {
+        let mut list = FreeList::new();
+        for i in 0..=FREE_LIST_CAPACITY {
+            list.push(i * 16, i * 16 + 8);
+        }
+        assert_eq!(list.spans().len(), FREE_LIST_CAPACITY);
+        assert_eq!(
+            list.spans().last(),
+            Some(&Span {
+                start: (FREE_LIST_CAPACITY - 1) * 16,
+                end: (FREE_LIST_CAPACITY - 1) * 16 + 8,
+            })
+        );
+    }
+}
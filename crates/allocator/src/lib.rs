@@ -17,6 +17,54 @@
 //! The default allocator is a bump allocator whose goal is to have a small size footprint. If you
 //! are not concerned about the size of your final Wasm binaries you may opt into using the more
 //! full-featured `wee_alloc` allocator by activating the `wee-alloc` crate feature.
+//!
+//! Contracts which must never grow their memory at runtime can instead activate the
+//! `static-arena` crate feature and install a [`StaticBumpAllocator`] of a fixed size as their
+//! global allocator.
+//!
+//! Contracts which must never observe stale heap contents can activate the `always-zero` crate
+//! feature, which makes every allocation behave like `alloc_zeroed`. Memory taken from freshly
+//! grown Wasm pages is zero initialized already, so this only costs an additional `memset` for
+//! bytes which are handed out a second time.
+//!
+//! With the `stats` crate feature enabled the allocator keeps track of its activity, see
+//! [`stats`]. The `debug-tags` crate feature additionally attributes memory to the tags given to
+//! `alloc_tagged`, which helps to find out which parts of a contract use how much memory.
+//!
+//! By default the bump allocator never reuses memory which has been deallocated. The `free-list`
+//! crate feature makes it remember a bounded number of freed spans and hand them out again to
+//! allocations which fit into them.
+//!
+//! The `size-classes` crate feature is a lighter alternative for the churn of small allocations:
+//! freed blocks are binned by the next power of two of their size and handed out again to later
+//! allocations of the same size class which fit into them.
+//!
+//! Runtimes which meter the memory a contract allocates over its whole lifetime can activate
+//! the `alloc-budget` crate feature and limit the bytes handed out via [`set_alloc_budget`].
+//!
+//! The `forbid-grow` crate feature allows to forbid the heap from growing at runtime, see
+//! [`set_forbid_grow`]. This is useful to assert that hot code paths do not request any new
+//! memory once they have been set up.
+//!
+//! With the `observer` crate feature enabled an [`AllocObserver`] can be registered to get
+//! notified about every allocation, deallocation and grow of the heap as well as every allocation
+//! which runs out of memory, e.g. to collect metrics.
+//!
+//! The `debug-log` crate feature keeps a log of the events of the heap, see [`event_log`]. Off-chain
+//! such a log can be replayed to reproduce out of memory errors deterministically.
+//!
+//! Off-chain the `debug-tracking` crate feature keeps track of all live allocations, which
+//! [`memory_map`] lists by address, e.g. to spot leaks.
+//!
+//! The `profile-peak` crate feature wraps the global allocator in a [`PeakLiveProfiler`], which
+//! publishes the peak number of live bytes, i.e. the most bytes the contract had allocated at
+//! once, to [`INK_HEAP_PEAK_LIVE`] for tooling to read after its execution.
+//! Similarly the `export-metrics` crate feature publishes the main counters of the [`stats`] to
+//! globals like [`INK_HEAP_ALLOCATIONS`], which the host can read once a call returns.
+//!
+//! The `init-check` crate feature provides [`check_init`], which asserts in debug builds that
+//! nothing has been allocated before the contract sets up its allocator. It also checks the page
+//! size of the memory whenever the heap grows, which traps in release builds as well.
 
 #![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(not(feature = "std"), feature(alloc_error_handler, core_intrinsics))]
@@ -29,12 +77,131 @@
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
 #[cfg(not(feature = "std"))]
-#[cfg(not(feature = "wee-alloc"))]
+#[cfg(not(any(
+    feature = "wee-alloc",
+    feature = "static-arena",
+    feature = "profile-peak"
+)))]
 #[global_allocator]
 static mut ALLOC: bump::BumpAllocator = bump::BumpAllocator {};
 
+#[cfg(not(feature = "std"))]
+#[cfg(all(
+    feature = "profile-peak",
+    not(any(feature = "wee-alloc", feature = "static-arena"))
+))]
+#[global_allocator]
+static ALLOC: peak::PeakLiveProfiler<bump::BumpAllocator> =
+    peak::PeakLiveProfiler::new(bump::BumpAllocator {});
+
+#[cfg(feature = "static-arena")]
+mod arena;
 #[cfg(not(feature = "wee-alloc"))]
 mod bump;
+#[cfg(all(feature = "std", not(feature = "wee-alloc")))]
+mod emulated;
+#[cfg(not(feature = "wee-alloc"))]
+mod error;
+#[cfg(all(feature = "debug-log", not(feature = "wee-alloc")))]
+mod event_log;
+#[cfg(all(feature = "std", not(feature = "wee-alloc")))]
+mod failing;
+#[cfg(all(feature = "free-list", not(feature = "wee-alloc")))]
+mod free_list;
+#[cfg(all(feature = "export-metrics", not(feature = "wee-alloc")))]
+mod metrics;
+#[cfg(all(feature = "observer", not(feature = "wee-alloc")))]
+mod observer;
+#[cfg(all(feature = "profile-peak", not(feature = "wee-alloc")))]
+mod peak;
+#[cfg(all(feature = "size-classes", not(feature = "wee-alloc")))]
+mod size_classes;
+#[cfg(all(feature = "stats", not(feature = "wee-alloc")))]
+mod stats;
+#[cfg(all(feature = "debug-tracking", not(feature = "wee-alloc")))]
+mod tracking;
+
+#[cfg(feature = "static-arena")]
+pub use self::arena::StaticBumpAllocator;
+#[cfg(all(feature = "init-check", not(feature = "wee-alloc")))]
+pub use self::bump::check_init;
+#[cfg(all(feature = "debug-tracking", not(feature = "wee-alloc")))]
+pub use self::bump::memory_map;
+#[cfg(all(feature = "alloc-budget", not(feature = "wee-alloc")))]
+pub use self::bump::set_alloc_budget;
+#[cfg(all(feature = "forbid-grow", not(feature = "wee-alloc")))]
+pub use self::bump::set_forbid_grow;
+#[cfg(not(feature = "wee-alloc"))]
+pub use self::bump::{
+    alloc_aligned,
+    alloc_filled,
+    alloc_value,
+    freeze,
+    resync,
+    thaw,
+    try_grow_pages,
+    Bump,
+    BumpAllocator,
+    BumpScope,
+    Config,
+    PageSource,
+};
+#[cfg(not(feature = "wee-alloc"))]
+pub use self::error::{
+    validate_align,
+    AllocError,
+};
+#[cfg(all(feature = "debug-log", feature = "std", not(feature = "wee-alloc")))]
+pub use self::event_log::{
+    replay,
+    ReplayResult,
+};
+#[cfg(all(feature = "export-metrics", not(feature = "wee-alloc")))]
+pub use self::metrics::{
+    INK_HEAP_ALLOCATIONS,
+    INK_HEAP_BYTES,
+    INK_HEAP_PAGES_REQUESTED,
+};
+#[cfg(all(feature = "profile-peak", not(feature = "wee-alloc")))]
+pub use self::peak::{
+    PeakLiveProfiler,
+    INK_HEAP_PEAK_LIVE,
+};
+#[cfg(all(feature = "debug-tags", not(feature = "wee-alloc")))]
+pub use self::{
+    bump::alloc_tagged,
+    stats::{
+        TagStats,
+        MAX_TAGS,
+    },
+};
+#[cfg(all(feature = "debug-log", not(feature = "wee-alloc")))]
+pub use self::{
+    bump::event_log,
+    event_log::{
+        AllocEvent,
+        EventLog,
+        EVENT_LOG_CAPACITY,
+    },
+};
+#[cfg(all(feature = "observer", not(feature = "wee-alloc")))]
+pub use self::{
+    bump::set_observer,
+    observer::AllocObserver,
+};
+#[cfg(all(feature = "stats", not(feature = "wee-alloc")))]
+pub use self::{
+    bump::{
+        clear_stats,
+        stats,
+    },
+    stats::AllocStats,
+};
+#[cfg(all(feature = "std", not(feature = "wee-alloc")))]
+pub use self::{
+    emulated::EmulatedHeap,
+    failing::FailingPages,
+};
 
 #[cfg(not(feature = "std"))]
 mod handlers;
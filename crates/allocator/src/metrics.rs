@@ -0,0 +1,45 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Publishes the statistics of the global heap to fixed globals.
+//!
+//! Only available if the `export-metrics` crate feature is enabled. On `wasm32` the globals are
+//! exported symbols, from where the host can read them out of the memory of the contract once a
+//! call returns, without the contract having to call any host functions. Off-chain they are
+//! plain statics.
+
+use crate::stats::AllocStats;
+use core::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
+
+/// The number of successful allocations of the global heap, see [`AllocStats::allocations`].
+#[cfg_attr(target_arch = "wasm32", no_mangle)]
+pub static INK_HEAP_ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of bytes handed out by the global heap, see [`AllocStats::bytes_allocated`].
+#[cfg_attr(target_arch = "wasm32", no_mangle)]
+pub static INK_HEAP_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+/// The number of pages requested by the global heap, see [`AllocStats::pages_requested`].
+#[cfg_attr(target_arch = "wasm32", no_mangle)]
+pub static INK_HEAP_PAGES_REQUESTED: AtomicUsize = AtomicUsize::new(0);
+
+/// Copies the counters of `stats` to the exported globals.
+pub(crate) fn publish(stats: &AllocStats) {
+    INK_HEAP_ALLOCATIONS.store(stats.allocations, Ordering::Relaxed);
+    INK_HEAP_BYTES.store(stats.bytes_allocated, Ordering::Relaxed);
+    INK_HEAP_PAGES_REQUESTED.store(stats.pages_requested, Ordering::Relaxed);
+}
@@ -0,0 +1,42 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Hooks for instrumenting the activity of the bump allocator.
+//!
+//! Only available if the `observer` crate feature is enabled, so that allocators without an
+//! observer do not pay for the check.
+
+use core::alloc::Layout;
+
+/// Gets notified about the activity of an allocator.
+///
+/// All methods do nothing by default, so implementations only need to provide the ones they
+/// are interested in. Since the allocator might be the global one, implementations must not
+/// allocate any memory themselves.
+pub trait AllocObserver {
+    /// Called after an allocation of the given `Layout` has been handed out at `start`.
+    fn on_alloc(&self, _start: usize, _layout: Layout) {}
+
+    /// Called when the allocation of the given `Layout` at `start` is released.
+    fn on_dealloc(&self, _start: usize, _layout: Layout) {}
+
+    /// Called after the heap has been grown by `pages` pages starting at `page_start`.
+    fn on_grow(&self, _page_start: usize, _pages: usize) {}
+
+    /// Called when an allocation of the given `Layout` fails since the heap cannot be grown any
+    /// further.
+    ///
+    /// Allocations which are refused while the heap is frozen are not reported.
+    fn on_oom(&self, _layout: Layout) {}
+}
@@ -0,0 +1,245 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Profiling of the peak number of live bytes of a contract.
+//!
+//! Only available if the `profile-peak` crate feature is enabled. The peak is published to
+//! [`INK_HEAP_PEAK_LIVE`], which is an exported symbol on `wasm32`, from where tooling can read
+//! it out of the memory of the contract once it has been executed.
+//!
+//! The profiler counts the bytes requested by the live allocations, not the memory used by the
+//! heap: padding for alignment and memory the wrapped allocator does not reclaim are not included.
+//! The bump allocator never shrinks, so the memory it uses is given by the number of pages it
+//! requested instead, see `AllocStats::pages_requested`.
+
+use core::{
+    alloc::{
+        GlobalAlloc,
+        Layout,
+    },
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+/// The highest number of live bytes, i.e. bytes which have been in use at the same time.
+///
+/// Updated by every [`PeakLiveProfiler`] whenever it observes a new peak, so with several
+/// profilers this is the highest peak of any of them.
+#[cfg_attr(target_arch = "wasm32", no_mangle)]
+pub static INK_HEAP_PEAK_LIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// Wraps a [`GlobalAlloc`] and keeps track of the peak number of live bytes.
+///
+/// A byte is in use from the moment it is allocated until it is deallocated again, regardless
+/// of whether the wrapped allocator reclaims it. Only the `size` of each `Layout` is counted, so
+/// the peak is a lower bound of the memory the wrapped allocator actually needed.
+pub struct PeakLiveProfiler<A> {
+    alloc: A,
+    /// The number of bytes currently in use.
+    live: AtomicUsize,
+    /// The highest number of bytes which have been in use at the same time.
+    peak: AtomicUsize,
+}
+
+impl<A> PeakLiveProfiler<A> {
+    /// Wraps `alloc` without any bytes in use yet.
+    pub const fn new(alloc: A) -> Self {
+        Self {
+            alloc,
+            live: AtomicUsize::new(0),
+            peak: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of bytes currently in use.
+    pub fn live(&self) -> usize {
+        self.live.load(Ordering::Relaxed)
+    }
+
+    /// Returns the highest number of bytes which have been in use at the same time.
+    pub fn peak(&self) -> usize {
+        self.peak.load(Ordering::Relaxed)
+    }
+
+    /// Applies `update` to the number of bytes in use, publishing any new peak.
+    fn record(&self, update: impl Fn(usize) -> usize) {
+        let previous = self
+            .live
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |live| {
+                Some(update(live))
+            })
+            .unwrap_or_else(|live| live);
+        let live = update(previous);
+        self.peak.fetch_max(live, Ordering::Relaxed);
+        INK_HEAP_PEAK_LIVE.fetch_max(live, Ordering::Relaxed);
+    }
+}
+
+unsafe impl<A> GlobalAlloc for PeakLiveProfiler<A>
+where
+    A: GlobalAlloc,
+{
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.alloc.alloc(layout);
+        if !ptr.is_null() {
+            self.record(|live| live.saturating_add(layout.size()));
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.alloc.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.record(|live| live.saturating_add(layout.size()));
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.alloc.dealloc(ptr, layout);
+        self.record(|live| live.saturating_sub(layout.size()));
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = self.alloc.realloc(ptr, layout, new_size);
+        if !new_ptr.is_null() {
+            self.record(|live| {
+                live.saturating_sub(layout.size()).saturating_add(new_size)
+            });
+        }
+        new_ptr
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bump::{
+            Bump,
+            PAGE_SIZE,
+        },
+        emulated::EmulatedHeap,
+    };
+    use core::cell::RefCell;
+
+    /// A bump allocator on an emulated heap which can be used through [`GlobalAlloc`].
+    struct Heap(RefCell<Bump<EmulatedHeap>>);
+
+    impl Heap {
+        fn new(pages: usize) -> Self {
+            Self(RefCell::new(Bump::new(EmulatedHeap::new(pages))))
+        }
+    }
+
+    unsafe impl GlobalAlloc for Heap {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            self.0
+                .borrow_mut()
+                .alloc(layout)
+                .map_or(core::ptr::null_mut(), |start| start as *mut u8)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            self.0.borrow_mut().dealloc(ptr as usize, layout)
+        }
+    }
+
+    #[test]
+    fn peak_matches_the_high_water_mark() {
+        let profiler = PeakLiveProfiler::new(Heap::new(2));
+        let layouts = [
+            Layout::new::<[u8; 1024]>(),
+            Layout::new::<u64>(),
+            Layout::new::<[u64; 8]>(),
+        ];
+
+        let ptrs = layouts
+            .iter()
+            .map(|&layout| unsafe { profiler.alloc(layout) })
+            .collect::<Vec<_>>();
+        assert_eq!(profiler.peak(), 1024 + 8 + 64);
+        // Without any deallocations all bytes the heap has been bumped by are in use
+        let bumped = ptrs[2] as usize + 64 - ptrs[0] as usize;
+        assert_eq!(profiler.peak(), bumped);
+
+        for (&ptr, &layout) in ptrs.iter().zip(&layouts) {
+            unsafe { profiler.dealloc(ptr, layout) };
+        }
+        assert_eq!(profiler.live(), 0);
+        assert_eq!(profiler.peak(), 1024 + 8 + 64);
+        assert!(INK_HEAP_PEAK_LIVE.load(Ordering::Relaxed) >= profiler.peak());
+    }
+
+    #[test]
+    fn peak_does_not_include_padding() {
+        let profiler = PeakLiveProfiler::new(Heap::new(2));
+        let layouts = [
+            Layout::new::<u8>(),
+            Layout::from_size_align(3, 16).unwrap(),
+            Layout::new::<u64>(),
+        ];
+
+        let ptrs = layouts
+            .iter()
+            .map(|&layout| unsafe { profiler.alloc(layout) })
+            .collect::<Vec<_>>();
+        assert_eq!(profiler.peak(), 1 + 3 + 8);
+        // The heap has been bumped further to satisfy the alignments
+        let bumped = ptrs[2] as usize + 8 - ptrs[0] as usize;
+        assert!(bumped > profiler.peak());
+    }
+
+    #[test]
+    fn deallocating_unrecorded_bytes_does_not_underflow() {
+        let heap = Heap::new(1);
+        let layout = Layout::new::<u64>();
+        let ptr = unsafe { heap.alloc(layout) };
+
+        // The allocation has been handed out before the heap has been wrapped
+        let profiler = PeakLiveProfiler::new(heap);
+        unsafe { profiler.dealloc(ptr, layout) };
+        assert_eq!(profiler.live(), 0);
+        assert_eq!(profiler.peak(), 0);
+    }
+
+    #[test]
+    fn peak_tracks_reallocations() {
+        let profiler = PeakLiveProfiler::new(Heap::new(2));
+        let layout = Layout::new::<[u8; 64]>();
+
+        let ptr = unsafe { profiler.alloc(layout) };
+        // The default `realloc` holds both allocations while copying
+        let ptr = unsafe { profiler.realloc(ptr, layout, 256) };
+        assert_eq!(profiler.live(), 256);
+        assert_eq!(profiler.peak(), 256);
+
+        let ptr = unsafe { profiler.realloc(ptr, Layout::new::<[u8; 256]>(), 16) };
+        assert!(!ptr.is_null());
+        assert_eq!(profiler.live(), 16);
+        assert_eq!(profiler.peak(), 256);
+    }
+
+    #[test]
+    fn failed_allocations_are_not_counted() {
+        let profiler = PeakLiveProfiler::new(Heap::new(1));
+
+        let ptr = unsafe { profiler.alloc(Layout::new::<[u8; 2 * PAGE_SIZE]>()) };
+        assert!(ptr.is_null());
+        assert_eq!(profiler.live(), 0);
+        assert_eq!(profiler.peak(), 0);
+    }
+}
@@ -0,0 +1,255 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bins of freed blocks which are handed out again to allocations of the same size class.
+//!
+//! Only used if the `size-classes` crate feature is enabled. Freed blocks of up to
+//! [`MAX_SIZE_CLASS`] bytes are binned by their size class, the next power of two of their size,
+//! so that a later allocation only has to look through the few blocks of its own class. The
+//! allocations themselves are not rounded up, a block just has to be at least as large as the
+//! allocation it is handed out to. Larger allocations are bumped just like without the feature.
+//!
+//! Each bin has a fixed capacity so that the bins do not need any heap memory themselves; blocks
+//! which do not fit into their bin any more are lost.
+
+/// The largest size class, larger allocations are never binned.
+pub(crate) const MAX_SIZE_CLASS: usize = 512;
+
+/// The maximum number of freed blocks which are remembered per size class.
+pub(crate) const BIN_CAPACITY: usize = 8;
+
+/// The number of size classes, one for every power of two up to [`MAX_SIZE_CLASS`].
+const CLASSES: usize = MAX_SIZE_CLASS.trailing_zeros() as usize + 1;
+
+/// Returns the size class of a block of `size` bytes.
+///
+/// Returns `None` for empty blocks and blocks larger than [`MAX_SIZE_CLASS`], which do not
+/// belong to any class.
+pub(crate) fn size_class(size: usize) -> Option<usize> {
+    if size == 0 || size > MAX_SIZE_CLASS {
+        return None
+    }
+    Some(size.next_power_of_two())
+}
+
+/// The freed blocks of a single size class, the most recently freed one last.
+#[derive(Debug, Copy, Clone)]
+struct Bin {
+    starts: [usize; BIN_CAPACITY],
+    sizes: [usize; BIN_CAPACITY],
+    len: usize,
+}
+
+/// The freed blocks of the heap, binned by their size class.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct SizeClasses {
+    bins: [Bin; CLASSES],
+}
+
+impl SizeClasses {
+    /// Creates empty bins.
+    pub const fn new() -> Self {
+        Self {
+            bins: [Bin {
+                starts: [0; BIN_CAPACITY],
+                sizes: [0; BIN_CAPACITY],
+                len: 0,
+            }; CLASSES],
+        }
+    }
+
+    /// Returns the starts of the freed blocks of the size class of `size`.
+    #[cfg(test)]
+    pub fn blocks(&self, size: usize) -> &[usize] {
+        match self.bin(size) {
+            Some(index) => &self.bins[index].starts[..self.bins[index].len],
+            None => &[],
+        }
+    }
+
+    /// Forgets about all freed blocks.
+    pub fn clear(&mut self) {
+        for bin in &mut self.bins {
+            bin.len = 0;
+        }
+    }
+
+    /// Puts the freed block of `size` bytes starting at `start` into the bin of its size class.
+    ///
+    /// Returns `false` if `size` does not belong to any size class or its bin is full already,
+    /// in which case the block is left to the caller.
+    pub fn push(&mut self, start: usize, size: usize) -> bool {
+        let bin = match self.bin(size) {
+            Some(index) => &mut self.bins[index],
+            None => return false,
+        };
+        if bin.len == BIN_CAPACITY {
+            return false
+        }
+        bin.starts[bin.len] = start;
+        bin.sizes[bin.len] = size;
+        bin.len += 1;
+        true
+    }
+
+    /// Takes the most recently freed block of the size class of `size` which is aligned to
+    /// `align` and holds at least `size` bytes.
+    ///
+    /// The bytes of the block beyond `size` are put back into the bins as a block of their own.
+    pub fn take(&mut self, size: usize, align: usize) -> Option<usize> {
+        let (index, position) = self.find(size, align)?;
+
+        let bin = &mut self.bins[index];
+        let start = bin.starts[position];
+        let block_size = bin.sizes[position];
+        bin.starts.copy_within(position + 1..bin.len, position);
+        bin.sizes.copy_within(position + 1..bin.len, position);
+        bin.len -= 1;
+        self.push(start + size, block_size - size);
+
+        Some(start)
+    }
+
+    /// Returns whether a block for `size` bytes aligned to `align` can be taken, see
+    /// [`SizeClasses::take`].
+    pub fn fits(&self, size: usize, align: usize) -> bool {
+        self.find(size, align).is_some()
+    }
+
+    /// Finds the block [`SizeClasses::take`] would hand out.
+    ///
+    /// Returns the index of its bin together with its position within the bin.
+    fn find(&self, size: usize, align: usize) -> Option<(usize, usize)> {
+        let index = self.bin(size)?;
+        let bin = &self.bins[index];
+        (0..bin.len)
+            .rev()
+            .find(|&position| {
+                bin.starts[position].is_multiple_of(align) && bin.sizes[position] >= size
+            })
+            .map(|position| (index, position))
+    }
+
+    /// Returns the index of the bin of blocks of `size` bytes, if `size` belongs to a size
+    /// class.
+    fn bin(&self, size: usize) -> Option<usize> {
+        size_class(size).map(|class| class.trailing_zeros() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rounds_up_to_size_classes() {
+        assert_eq!(size_class(0), None);
+        assert_eq!(size_class(1), Some(1));
+        assert_eq!(size_class(3), Some(4));
+        assert_eq!(size_class(64), Some(64));
+        assert_eq!(size_class(65), Some(128));
+        assert_eq!(size_class(MAX_SIZE_CLASS), Some(MAX_SIZE_CLASS));
+        assert_eq!(size_class(MAX_SIZE_CLASS + 1), None);
+    }
+
+    #[test]
+    fn takes_the_most_recently_freed_block() {
+        let mut classes = SizeClasses::new();
+        assert!(classes.push(0, 32));
+        assert!(classes.push(64, 32));
+        assert!(classes.push(128, 16));
+
+        assert_eq!(classes.take(32, 8), Some(64));
+        assert_eq!(classes.take(32, 8), Some(0));
+        assert_eq!(classes.take(32, 8), None);
+        assert_eq!(classes.blocks(16), &[128]);
+    }
+
+    #[test]
+    fn only_takes_aligned_blocks() {
+        let mut classes = SizeClasses::new();
+        assert!(classes.push(32, 32));
+
+        assert!(!classes.fits(32, 64));
+        assert_eq!(classes.take(32, 64), None);
+        assert_eq!(classes.take(32, 32), Some(32));
+    }
+
+    #[test]
+    fn looks_through_the_whole_bin() {
+        let mut classes = SizeClasses::new();
+        assert!(classes.push(64, 32));
+        assert!(classes.push(32, 32));
+        assert!(classes.push(96, 32));
+
+        assert_eq!(classes.take(32, 64), Some(64));
+        assert_eq!(classes.blocks(32), &[32, 96]);
+    }
+
+    #[test]
+    fn bins_blocks_which_are_no_power_of_two() {
+        let mut classes = SizeClasses::new();
+        assert!(classes.push(0, 24));
+        assert!(classes.push(64, 20));
+
+        assert_eq!(classes.blocks(32), &[0, 64]);
+        // The most recently freed block is too small for the request
+        assert_eq!(classes.take(24, 8), Some(0));
+        assert_eq!(classes.take(24, 8), None);
+        assert_eq!(classes.take(20, 4), Some(64));
+    }
+
+    #[test]
+    fn puts_back_the_rest_of_a_larger_block() {
+        let mut classes = SizeClasses::new();
+        assert!(classes.push(0, 32));
+
+        assert_eq!(classes.take(24, 8), Some(0));
+        assert!(classes.blocks(32).is_empty());
+        assert_eq!(classes.blocks(8), &[24]);
+    }
+
+    #[test]
+    fn rejects_sizes_which_are_no_size_class() {
+        let mut classes = SizeClasses::new();
+
+        assert!(!classes.push(0, 2 * MAX_SIZE_CLASS));
+        assert!(!classes.push(0, 0));
+        assert_eq!(classes.take(2 * MAX_SIZE_CLASS, 8), None);
+    }
+
+    #[test]
+    fn leaves_blocks_beyond_capacity_to_the_caller() {
+        let mut classes = SizeClasses::new();
+        for i in 0..BIN_CAPACITY {
+            assert!(classes.push(i * 8, 8));
+        }
+
+        assert!(!classes.push(BIN_CAPACITY * 8, 8));
+        assert_eq!(classes.blocks(8).len(), BIN_CAPACITY);
+        // The other bins are not affected
+        assert!(classes.push(0, 16));
+    }
+
+    #[test]
+    fn clear_forgets_all_blocks() {
+        let mut classes = SizeClasses::new();
+        classes.push(0, 8);
+        classes.push(32, 16);
+
+        classes.clear();
+        assert!(classes.blocks(8).is_empty());
+        assert!(classes.blocks(16).is_empty());
+    }
+}
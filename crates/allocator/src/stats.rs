@@ -0,0 +1,141 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Statistics about the activity of the bump allocator.
+//!
+//! Only collected if the `stats` crate feature is enabled.
+
+/// The maximum number of distinct tags whose memory usage is tracked separately.
+#[cfg(feature = "debug-tags")]
+pub const MAX_TAGS: usize = 16;
+
+/// Counters describing the activity of an allocator since its creation.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct AllocStats {
+    /// The number of successful allocations.
+    pub allocations: usize,
+    /// The number of allocations which failed since there was not enough memory left.
+    pub failed_allocations: usize,
+    /// The total number of bytes handed out, including the padding of each allocation.
+    pub bytes_allocated: usize,
+    /// The number of pages requested from the page source.
+    pub pages_requested: usize,
+    /// The number of bytes left unused at the end of the heap since an allocation did not fit
+    /// and had to move on to new pages.
+    pub bytes_fragmented: usize,
+    /// The number of bytes skipped in front of allocations to satisfy their alignment.
+    ///
+    /// Reordering allocations or reducing their alignment might reclaim these bytes.
+    pub alignment_waste: usize,
+    /// The number of bytes copied by reallocations which could not grow in place.
+    pub bytes_copied: usize,
+    /// The number of deallocations, whether or not their memory could be reused.
+    pub deallocations: usize,
+    /// The total number of bytes released by deallocations, including the padding of each
+    /// allocation just like `bytes_allocated`.
+    pub bytes_freed: usize,
+    /// The number of bytes handed out per tag, see `alloc_tagged`.
+    #[cfg(feature = "debug-tags")]
+    pub tags: TagStats,
+}
+
+impl AllocStats {
+    /// Creates statistics with all counters set to zero.
+    pub const fn new() -> Self {
+        Self {
+            allocations: 0,
+            failed_allocations: 0,
+            bytes_allocated: 0,
+            pages_requested: 0,
+            bytes_fragmented: 0,
+            alignment_waste: 0,
+            bytes_copied: 0,
+            deallocations: 0,
+            bytes_freed: 0,
+            #[cfg(feature = "debug-tags")]
+            tags: TagStats::new(),
+        }
+    }
+
+    /// Returns the number of allocations which have not been deallocated (yet).
+    ///
+    /// A number which keeps growing over calls which should not retain any memory hints at a
+    /// leak.
+    pub fn outstanding_allocations(&self) -> usize {
+        self.allocations.saturating_sub(self.deallocations)
+    }
+
+    /// Returns the number of bytes which have not been deallocated (yet).
+    pub fn outstanding_bytes(&self) -> usize {
+        self.bytes_allocated.saturating_sub(self.bytes_freed)
+    }
+}
+
+/// The number of bytes handed out for each tag of tagged allocations.
+///
+/// Up to [`MAX_TAGS`] distinct tags are tracked, the bytes of any further tags are accumulated
+/// as untracked.
+#[cfg(feature = "debug-tags")]
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct TagStats {
+    tags: [Option<(&'static str, usize)>; MAX_TAGS],
+    untracked: usize,
+}
+
+#[cfg(feature = "debug-tags")]
+impl TagStats {
+    /// Creates an empty table of tags.
+    pub const fn new() -> Self {
+        Self {
+            tags: [None; MAX_TAGS],
+            untracked: 0,
+        }
+    }
+
+    /// Adds `bytes` to the total of `tag`.
+    pub(crate) fn record(&mut self, tag: &'static str, bytes: usize) {
+        for entry in self.tags.iter_mut() {
+            match entry {
+                Some((existing, total)) if *existing == tag => {
+                    *total += bytes;
+                    return
+                }
+                Some(_) => continue,
+                None => {
+                    *entry = Some((tag, bytes));
+                    return
+                }
+            }
+        }
+        self.untracked += bytes;
+    }
+
+    /// Returns the number of bytes handed out for `tag`.
+    pub fn bytes(&self, tag: &str) -> usize {
+        self.iter()
+            .find(|(existing, _)| *existing == tag)
+            .map(|(_, total)| total)
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of bytes handed out for tags which did not fit into the table.
+    pub fn untracked(&self) -> usize {
+        self.untracked
+    }
+
+    /// Iterates over all tracked tags and their number of bytes, in order of first use.
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, usize)> + '_ {
+        self.tags.iter().flatten().copied()
+    }
+}
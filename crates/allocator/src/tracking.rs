@@ -0,0 +1,158 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Keeps track of the live allocations of a heap for debugging.
+//!
+//! Only used if the `debug-tracking` crate feature is enabled. The allocations are tracked in a
+//! table of a fixed capacity, since the table must not allocate from the heap it keeps track of;
+//! allocations which do not fit into it any more are only counted.
+
+/// The maximum number of live allocations which are tracked at the same time.
+pub(crate) const TRACKING_CAPACITY: usize = 256;
+
+/// A live allocation: its start, its size and the tag it has been allocated with.
+type Entry = (usize, usize, Option<&'static str>);
+
+/// The live allocations of a heap, in no particular order.
+#[derive(Debug, Copy, Clone)]
+pub(crate) struct LiveAllocations {
+    entries: [Entry; TRACKING_CAPACITY],
+    len: usize,
+    untracked: usize,
+}
+
+impl LiveAllocations {
+    /// Creates an empty table.
+    pub const fn new() -> Self {
+        Self {
+            entries: [(0, 0, None); TRACKING_CAPACITY],
+            len: 0,
+            untracked: 0,
+        }
+    }
+
+    /// Returns the tracked allocations ordered by their start.
+    pub fn sorted(&self) -> Vec<Entry> {
+        let mut entries = self.entries[..self.len].to_vec();
+        entries.sort_unstable_by_key(|&(start, _, _)| start);
+        entries
+    }
+
+    /// Returns the number of allocations which did not fit into the table.
+    pub fn untracked(&self) -> usize {
+        self.untracked
+    }
+
+    /// Starts tracking the allocation of `size` bytes at `start`.
+    pub fn insert(&mut self, start: usize, size: usize) {
+        if self.len == TRACKING_CAPACITY {
+            self.untracked += 1;
+            return
+        }
+        self.entries[self.len] = (start, size, None);
+        self.len += 1;
+    }
+
+    /// Stops tracking the allocation at `start`.
+    pub fn remove(&mut self, start: usize) {
+        if let Some(index) = self.find(start) {
+            self.len -= 1;
+            self.entries[index] = self.entries[self.len];
+        }
+    }
+
+    /// Updates the size of the allocation at `start`.
+    pub fn resize(&mut self, start: usize, size: usize) {
+        if let Some(index) = self.find(start) {
+            self.entries[index].1 = size;
+        }
+    }
+
+    /// Attributes the allocation at `start` to `tag`.
+    #[cfg(any(test, feature = "debug-tags"))]
+    pub fn tag(&mut self, start: usize, tag: &'static str) {
+        if let Some(index) = self.find(start) {
+            self.entries[index].2 = Some(tag);
+        }
+    }
+
+    /// Stops tracking all allocations at or above `start`.
+    pub fn forget_from(&mut self, start: usize) {
+        let mut len = 0;
+        for index in 0..self.len {
+            if self.entries[index].0 < start {
+                self.entries[len] = self.entries[index];
+                len += 1;
+            }
+        }
+        self.len = len;
+    }
+
+    /// Stops tracking any allocations.
+    pub fn clear(&mut self) {
+        self.len = 0;
+        self.untracked = 0;
+    }
+
+    /// Returns the index of the allocation at `start`.
+    fn find(&self, start: usize) -> Option<usize> {
+        self.entries[..self.len]
+            .iter()
+            .position(|&(entry_start, _, _)| entry_start == start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_allocations_by_address() {
+        let mut live = LiveAllocations::new();
+        live.insert(64, 8);
+        live.insert(0, 16);
+        live.insert(32, 4);
+        live.remove(0);
+        live.tag(32, "tag");
+        live.resize(64, 2);
+
+        assert_eq!(live.sorted(), vec![(32, 4, Some("tag")), (64, 2, None)]);
+    }
+
+    #[test]
+    fn counts_allocations_beyond_capacity() {
+        let mut live = LiveAllocations::new();
+        for i in 0..=TRACKING_CAPACITY {
+            live.insert(i * 8, 8);
+        }
+
+        assert_eq!(live.sorted().len(), TRACKING_CAPACITY);
+        assert_eq!(live.untracked(), 1);
+
+        live.clear();
+        assert!(live.sorted().is_empty());
+        assert_eq!(live.untracked(), 0);
+    }
+
+    #[test]
+    fn forgets_allocations_from_an_address() {
+        let mut live = LiveAllocations::new();
+        live.insert(64, 8);
+        live.insert(0, 8);
+        live.insert(32, 8);
+
+        live.forget_from(32);
+        assert_eq!(live.sorted(), vec![(0, 8, None)]);
+    }
+}
@@ -0,0 +1,137 @@
+// Copyright 2018-2021 Parity Technologies (UK) Ltd.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Exercises the `BumpAllocator` as the global allocator of a real program.
+//!
+//! Off-chain the heap lives in an emulation of the Wasm memory. This test runs without the default
+//! test harness, so that nothing but the workloads below allocates from the heap while the
+//! statistics are compared.
+
+#[cfg(not(feature = "wee-alloc"))]
+mod bump {
+    use ink_allocator::BumpAllocator;
+
+    #[global_allocator]
+    static ALLOC: BumpAllocator = BumpAllocator;
+
+    /// A page in Wasm is `64KiB`
+    const PAGE_SIZE: usize = 64 * 1024;
+
+    fn vec_growth() {
+        let mut vec = Vec::new();
+        for i in 0..10_000u64 {
+            vec.push(i);
+        }
+        assert!(vec.iter().copied().eq(0..10_000));
+    }
+
+    fn strings() {
+        let mut string = String::new();
+        for i in 0..1_000 {
+            string.push_str(&i.to_string());
+        }
+        assert!(string.starts_with("0123456789101112"));
+        assert!(string.ends_with("998999"));
+    }
+
+    fn boxes() {
+        let boxes = (0..100u128).map(Box::new).collect::<Vec<_>>();
+        for (i, boxed) in boxes.iter().enumerate() {
+            assert_eq!(**boxed, i as u128);
+            assert_eq!(
+                &**boxed as *const u128 as usize % core::mem::align_of::<u128>(),
+                0
+            );
+        }
+    }
+
+    fn large_allocation() {
+        let large = vec![0u8; 4 * PAGE_SIZE + 1];
+        assert!(large.iter().all(|&byte| byte == 0));
+
+        let mut large = large;
+        large.iter_mut().for_each(|byte| *byte = 0xFF);
+        assert!(large.iter().all(|&byte| byte == 0xFF));
+    }
+
+    fn threads() {
+        let threads = (0..4u64)
+            .map(|t| {
+                std::thread::spawn(move || {
+                    (0..1_000)
+                        .map(|i| Box::new(t * 1_000 + i))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect::<Vec<_>>();
+        for (t, thread) in threads.into_iter().enumerate() {
+            let boxes = thread.join().unwrap();
+            let t = t as u64;
+            assert!(boxes
+                .iter()
+                .map(|boxed| **boxed)
+                .eq(t * 1_000..(t + 1) * 1_000));
+        }
+    }
+
+    pub fn run() {
+        #[cfg(feature = "stats")]
+        let before = ink_allocator::stats();
+
+        vec_growth();
+        strings();
+        boxes();
+        large_allocation();
+        threads();
+
+        #[cfg(feature = "stats")]
+        {
+            let after = ink_allocator::stats();
+            assert!(after.allocations > before.allocations + 1_000);
+            assert!(after.bytes_allocated > before.bytes_allocated + 4 * PAGE_SIZE);
+            assert!(after.pages_requested >= before.pages_requested + 5);
+            assert_eq!(after.failed_allocations, before.failed_allocations);
+        }
+
+        #[cfg(feature = "export-metrics")]
+        {
+            use std::sync::atomic::Ordering;
+
+            let exported = || {
+                (
+                    ink_allocator::INK_HEAP_ALLOCATIONS.load(Ordering::Relaxed),
+                    ink_allocator::INK_HEAP_BYTES.load(Ordering::Relaxed),
+                    ink_allocator::INK_HEAP_PAGES_REQUESTED.load(Ordering::Relaxed),
+                )
+            };
+            let stats = ink_allocator::stats();
+            assert_eq!(
+                exported(),
+                (
+                    stats.allocations,
+                    stats.bytes_allocated,
+                    stats.pages_requested
+                )
+            );
+
+            ink_allocator::clear_stats();
+            assert_eq!(exported(), (0, 0, 0));
+        }
+    }
+}
+
+fn main() {
+    #[cfg(not(feature = "wee-alloc"))]
+    bump::run();
+}